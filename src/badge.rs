@@ -0,0 +1,154 @@
+use std::fmt::Write;
+
+use crate::get_stats::Stats;
+use crate::html::{escape, PALETTE, REST_COLOR};
+
+const HEIGHT: u32 = 20;
+/// Rough width of a character of 11px Verdana, which badges are set in.
+const CHAR_WIDTH: u32 = 7;
+const PADDING: u32 = 10;
+const LABEL_COLOR: &str = "#555";
+const VALUE_COLOR: &str = "#007ec6";
+/// Width of the bar in the languages badge.
+const BAR_WIDTH: u32 = 240;
+
+impl Stats {
+    /// Renders a shields-style badge with the total lines of code, e.g. `lines of code | 12.3k`.
+    pub fn to_svg_badge(&self) -> String {
+        let label = "lines of code";
+        let value = human_count(self.total_loc);
+        let label_width = text_width(label);
+        let value_width = text_width(&value);
+
+        let mut svg = badge_start(label, &value, label_width + value_width);
+        let _ = writeln!(
+            svg,
+            "<rect x=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\"/>",
+            label_width, value_width, HEIGHT, VALUE_COLOR
+        );
+        badge_end(&mut svg, label, label_width);
+        push_text(&mut svg, &value, label_width + value_width / 2);
+        svg.push_str("</g>\n</svg>\n");
+        svg
+    }
+
+    /// Renders a shields-style badge with a bar split between the languages by
+    /// their share of lines. Languages too narrow to fit their name only show it
+    /// when hovered.
+    pub fn to_svg_languages_badge(&self) -> String {
+        let label = "languages";
+        let label_width = text_width(label);
+
+        // the bar always starts with the biggest language
+        let langs_vec = self.ranked_langs();
+        let summary = langs_vec
+            .iter()
+            .map(|(lang, stat)| format!("{} {}%", lang, stat.percent))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        let mut svg = badge_start(label, &summary, label_width + BAR_WIDTH);
+        let _ = writeln!(
+            svg,
+            "<rect x=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\"/>",
+            label_width, BAR_WIDTH, HEIGHT, REST_COLOR
+        );
+        let mut segments = Vec::new();
+        let mut x = label_width;
+        for (i, (lang, stat)) in langs_vec.iter().enumerate() {
+            // the last segment fills the rest of the bar, so rounding leaves no gap
+            let rest = label_width + BAR_WIDTH - x;
+            let width = if i == langs_vec.len() - 1 {
+                rest
+            } else {
+                let share = stat.loc as f32 / self.total_loc.max(1) as f32;
+                ((share * BAR_WIDTH as f32).round() as u32).min(rest)
+            };
+            let color = PALETTE.get(i).copied().unwrap_or(REST_COLOR);
+            let _ = writeln!(
+                svg,
+                "<rect x=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\"><title>{} {}%</title></rect>",
+                x,
+                width,
+                HEIGHT,
+                color,
+                escape(lang),
+                stat.percent
+            );
+            if width >= text_width(lang) {
+                segments.push((lang, x + width / 2));
+            }
+            x += width;
+        }
+        badge_end(&mut svg, label, label_width);
+        for (lang, x) in segments {
+            push_text(&mut svg, lang, x);
+        }
+        svg.push_str("</g>\n</svg>\n");
+        svg
+    }
+}
+
+/// Shortens a count to at most 4 significant characters, e.g. `12.3k` or `4M`.
+pub fn human_count(count: usize) -> String {
+    let mut value = count as f64;
+    for unit in ["", "k", "M", "G"] {
+        // one decimal is only shown below 100
+        let shown = if unit.is_empty() || value >= 99.95 {
+            value.round()
+        } else {
+            (value * 10.0).round() / 10.0
+        };
+        if shown < 1000.0 || unit == "G" {
+            return format!("{}{}", shown, unit);
+        }
+        value /= 1000.0;
+    }
+    unreachable!()
+}
+
+fn text_width(text: &str) -> u32 {
+    text.chars().count() as u32 * CHAR_WIDTH + PADDING
+}
+
+/// Starts the badge, leaving the group of its backgrounds open.
+fn badge_start(label: &str, value: &str, width: u32) -> String {
+    let title = escape(&format!("{}: {}", label, value));
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" role=\"img\" aria-label=\"{t}\">\n<title>{t}</title>",
+        w = width,
+        h = HEIGHT,
+        t = title
+    );
+    svg.push_str("<linearGradient id=\"s\" x2=\"0\" y2=\"100%\"><stop offset=\"0\" stop-color=\"#bbb\" stop-opacity=\".1\"/><stop offset=\"1\" stop-opacity=\".1\"/></linearGradient>\n");
+    let _ = writeln!(
+        svg,
+        "<clipPath id=\"r\"><rect width=\"{}\" height=\"{}\" rx=\"3\" fill=\"#fff\"/></clipPath>\n<g clip-path=\"url(#r)\">",
+        width, HEIGHT
+    );
+    svg
+}
+
+/// Draws the label over the background, leaving the text group open for the values.
+fn badge_end(svg: &mut String, label: &str, label_width: u32) {
+    let _ = writeln!(
+        svg,
+        "<rect width=\"{}\" height=\"{}\" fill=\"{}\"/>\n<rect width=\"100%\" height=\"{}\" fill=\"url(#s)\"/>\n</g>",
+        label_width, HEIGHT, LABEL_COLOR, HEIGHT
+    );
+    svg.push_str("<g fill=\"#fff\" text-anchor=\"middle\" font-family=\"Verdana,Geneva,DejaVu Sans,sans-serif\" font-size=\"11\">\n");
+    push_text(svg, label, label_width / 2);
+}
+
+/// Writes centered text with a shadow below it.
+fn push_text(svg: &mut String, text: &str, x: u32) {
+    let text = escape(text);
+    let _ = writeln!(
+        svg,
+        "<text x=\"{x}\" y=\"15\" fill=\"#010101\" fill-opacity=\".3\">{t}</text><text x=\"{x}\" y=\"14\">{t}</text>",
+        x = x,
+        t = text
+    );
+}
@@ -0,0 +1,273 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::get_stats::{FileStat, LangStat, RootStats};
+
+/// Lines per language and per file, saved to compare later runs against.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Baseline {
+    pub total_loc: usize,
+    pub by_lang: BTreeMap<String, Lines>,
+    /// Keyed by path relative to the counted path, so that baselines taken
+    /// from another directory or checkout match. When several paths are
+    /// counted, each file's path starts with the name of its counted path.
+    pub files: BTreeMap<PathBuf, BaselineFile>,
+}
+
+/// Lines of a language or a file, split by kind.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Lines {
+    pub loc: usize,
+    pub code: usize,
+    pub comments: usize,
+    pub docs: usize,
+    pub blanks: usize,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct BaselineFile {
+    pub lang: String,
+    #[serde(flatten)]
+    pub lines: Lines,
+}
+
+/// How the lines changed since the baseline.
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct Comparison {
+    pub before: usize,
+    pub after: usize,
+    pub delta: i64,
+    /// Languages whose lines changed, the biggest change first.
+    pub by_lang: Vec<LangDelta>,
+    /// Files that were added, removed or changed, sorted by path.
+    pub files: Vec<FileDelta>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct LangDelta {
+    pub lang: String,
+    pub before: usize,
+    pub after: usize,
+    pub delta: i64,
+    #[serde(flatten)]
+    pub kinds: KindDeltas,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct FileDelta {
+    pub path: PathBuf,
+    pub lang: String,
+    pub change: FileChange,
+    pub before: usize,
+    pub after: usize,
+    pub delta: i64,
+    #[serde(flatten)]
+    pub kinds: KindDeltas,
+}
+
+/// Change in each kind of line, which can happen without a change in `delta`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize)]
+pub struct KindDeltas {
+    pub code: i64,
+    pub comments: i64,
+    pub docs: i64,
+    pub blanks: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FileChange {
+    Added,
+    Removed,
+    Changed,
+}
+
+impl Baseline {
+    /// Takes a snapshot of the counted paths, which need to list their files
+    /// (see `GetStatsOptions::files`).
+    pub fn new(roots: &[RootStats]) -> Self {
+        let mut baseline = Self::default();
+        for root in roots {
+            baseline.total_loc += root.stats.total_loc;
+            for (lang, stat) in &root.stats.by_lang {
+                baseline
+                    .by_lang
+                    .entry(lang.to_string())
+                    .or_default()
+                    .add(&Lines::from(stat));
+            }
+
+            let prefix = (roots.len() > 1).then(|| root_name(&root.path));
+            for file in &root.stats.files {
+                let relative = file.path.strip_prefix(&root.path).unwrap_or(&file.path);
+                let path = match (&prefix, relative.as_os_str().is_empty()) {
+                    (Some(prefix), _) => prefix.join(relative),
+                    // a counted file is keyed by its name
+                    (None, true) => file.path.file_name().map_or(relative.into(), Into::into),
+                    (None, false) => relative.to_path_buf(),
+                };
+                let baseline_file = BaselineFile {
+                    lang: file.lang.to_string(),
+                    lines: Lines::from(file),
+                };
+                baseline.files.insert(path, baseline_file);
+            }
+        }
+        baseline
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Could not read baseline {}", path.display()))?;
+        serde_json::from_str(&contents)
+            .with_context(|| format!("Could not parse baseline {}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        fs::write(path, json + "\n")
+            .with_context(|| format!("Could not write baseline {}", path.display()))
+    }
+
+    /// Compares a later snapshot against this one.
+    pub fn compare(&self, after: &Baseline) -> Comparison {
+        let mut by_lang: Vec<LangDelta> = self
+            .by_lang
+            .keys()
+            .chain(after.by_lang.keys())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .map(|lang| {
+                let before = self.by_lang.get(lang).copied().unwrap_or_default();
+                let after = after.by_lang.get(lang).copied().unwrap_or_default();
+                LangDelta {
+                    lang: lang.clone(),
+                    before: before.loc,
+                    after: after.loc,
+                    delta: delta(before.loc, after.loc),
+                    kinds: KindDeltas::new(&before, &after),
+                }
+            })
+            .filter(|lang| lang.kinds != KindDeltas::default())
+            .collect();
+        by_lang.sort_by_key(|lang| std::cmp::Reverse(lang.delta.abs()));
+
+        let mut files = Vec::new();
+        for (path, old) in &self.files {
+            match after.files.get(path) {
+                Some(new) if new.lines == old.lines => {}
+                Some(new) => files.push(FileDelta::new(
+                    path,
+                    &new.lang,
+                    FileChange::Changed,
+                    &old.lines,
+                    &new.lines,
+                )),
+                None => files.push(FileDelta::new(
+                    path,
+                    &old.lang,
+                    FileChange::Removed,
+                    &old.lines,
+                    &Lines::default(),
+                )),
+            }
+        }
+        for (path, new) in &after.files {
+            if !self.files.contains_key(path) {
+                files.push(FileDelta::new(
+                    path,
+                    &new.lang,
+                    FileChange::Added,
+                    &Lines::default(),
+                    &new.lines,
+                ));
+            }
+        }
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+
+        Comparison {
+            before: self.total_loc,
+            after: after.total_loc,
+            delta: delta(self.total_loc, after.total_loc),
+            by_lang,
+            files,
+        }
+    }
+}
+
+impl Lines {
+    fn add(&mut self, other: &Lines) {
+        self.loc += other.loc;
+        self.code += other.code;
+        self.comments += other.comments;
+        self.docs += other.docs;
+        self.blanks += other.blanks;
+    }
+}
+
+impl From<&LangStat> for Lines {
+    fn from(stat: &LangStat) -> Self {
+        Self {
+            loc: stat.loc,
+            code: stat.code,
+            comments: stat.comments,
+            docs: stat.docs,
+            blanks: stat.blanks,
+        }
+    }
+}
+
+impl From<&FileStat> for Lines {
+    fn from(file: &FileStat) -> Self {
+        Self {
+            loc: file.loc,
+            code: file.code,
+            comments: file.comments,
+            docs: file.docs,
+            blanks: file.blanks,
+        }
+    }
+}
+
+impl KindDeltas {
+    fn new(before: &Lines, after: &Lines) -> Self {
+        Self {
+            code: delta(before.code, after.code),
+            comments: delta(before.comments, after.comments),
+            docs: delta(before.docs, after.docs),
+            blanks: delta(before.blanks, after.blanks),
+        }
+    }
+}
+
+impl FileDelta {
+    fn new(path: &Path, lang: &str, change: FileChange, before: &Lines, after: &Lines) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            lang: lang.to_string(),
+            change,
+            before: before.loc,
+            after: after.loc,
+            delta: delta(before.loc, after.loc),
+            kinds: KindDeltas::new(before, after),
+        }
+    }
+}
+
+fn delta(before: usize, after: usize) -> i64 {
+    after as i64 - before as i64
+}
+
+/// The name of a counted path, which `.` or `..` do not have on their own.
+fn root_name(root: &Path) -> PathBuf {
+    let canonical = root.canonicalize().unwrap_or_else(|_| root.to_path_buf());
+    canonical
+        .file_name()
+        .map_or_else(|| root.to_path_buf(), PathBuf::from)
+}
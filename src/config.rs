@@ -0,0 +1,278 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+
+use crate::get_stats::{GetStatsOptions, SortOrder, Stats};
+use crate::langs::{DocSyntax, LangRegistry, LangSyntax, NO_DOCS};
+use crate::output::OutputFormat;
+
+/// Project-level settings read from a `.loc-stats.toml` file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    pub gitignore: Option<bool>,
+    pub linguist: Option<bool>,
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    /// Language names keyed by extension (without the dot).
+    pub extensions: BTreeMap<String, String>,
+    /// Language names keyed by glob, overriding detection for matching files.
+    pub languages: BTreeMap<String, String>,
+    /// Languages unknown to loc-stats, keyed by name.
+    pub custom_languages: BTreeMap<String, CustomLanguage>,
+    /// Heuristics keyed by extension (without the dot), tried before the
+    /// built-in ones for files sharing it.
+    pub heuristics: BTreeMap<String, Vec<CustomHeuristic>>,
+    pub format: Option<OutputFormat>,
+    pub sort: Option<SortOrder>,
+    pub thresholds: Thresholds,
+    /// Directory of the file, which its globs are relative to.
+    #[serde(skip)]
+    pub dir: Option<PathBuf>,
+}
+
+/// A language defined in the config file, along with its comment syntax.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct CustomLanguage {
+    /// Extensions (without the dot) of the language's files.
+    pub extensions: Vec<String>,
+    /// Exact names of the language's files.
+    pub filenames: Vec<String>,
+    pub line_comments: Vec<String>,
+    pub block_comments: Vec<(String, String)>,
+    pub nested: bool,
+    pub quotes: Vec<(String, String)>,
+    pub verbatim_quotes: Vec<(String, String)>,
+    pub doc_comments: Vec<String>,
+    pub doc_block_comments: Vec<(String, String)>,
+}
+
+/// Picks `language` for files whose contents match the regex `pattern`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct CustomHeuristic {
+    pub language: String,
+    pub pattern: String,
+}
+
+/// Limits the stats have to stay within.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Thresholds {
+    /// Upper limit of the total number of lines.
+    pub max_total_loc: Option<usize>,
+    /// Upper limits of a language's share of lines, in percent, keyed by language.
+    pub max_percent: BTreeMap<String, f32>,
+    /// Upper limit of the lines of a single file.
+    pub max_file_loc: Option<usize>,
+    /// Lower limit of the share of comment and doc lines among non-blank lines.
+    pub min_comment_ratio: Option<f32>,
+}
+
+impl Config {
+    pub const FILE_NAME: &'static str = ".loc-stats.toml";
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Could not read config file {}", path.display()))?;
+        let mut config: Self = toml::from_str(&contents)
+            .with_context(|| format!("Could not parse config file {}", path.display()))?;
+        config.dir = Some(match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _ => PathBuf::from("."),
+        });
+        Ok(config)
+    }
+
+    /// Looks for a `.loc-stats.toml` in `path` and its ancestors, returning the
+    /// closest one.
+    pub fn discover(path: &Path) -> Result<Option<Self>> {
+        match Self::find(path) {
+            Some(file) => Ok(Some(Self::load(&file)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn find(path: &Path) -> Option<PathBuf> {
+        let path = path.canonicalize().ok()?;
+        path.ancestors()
+            .map(|dir| dir.join(Self::FILE_NAME))
+            .find(|file| file.is_file())
+    }
+
+    pub fn stats_options(&self) -> Result<GetStatsOptions> {
+        let defaults = GetStatsOptions::default();
+
+        let mut langs = LangRegistry::new();
+        for (name, custom) in &self.custom_languages {
+            langs.add_language(name, custom.syntax());
+        }
+        for (name, custom) in &self.custom_languages {
+            for ext in &custom.extensions {
+                langs.add_extension(ext.trim_start_matches('.'), name)?;
+            }
+            for file_name in &custom.filenames {
+                langs.add_filename(file_name, name)?;
+            }
+        }
+        for (ext, name) in &self.extensions {
+            langs
+                .add_extension(ext.trim_start_matches('.'), name)
+                .map_err(|_| anyhow!("Unknown language in config: {}", name))?;
+        }
+        for (ext, heuristics) in &self.heuristics {
+            for heuristic in heuristics {
+                langs
+                    .add_heuristic(
+                        ext.trim_start_matches('.'),
+                        &heuristic.language,
+                        &heuristic.pattern,
+                    )
+                    .context("Invalid heuristic in config")?;
+            }
+        }
+
+        Ok(GetStatsOptions {
+            gitignore: self.gitignore.unwrap_or(defaults.gitignore),
+            linguist: self.linguist.unwrap_or(defaults.linguist),
+            config_include: self.include.clone(),
+            config_exclude: self.exclude.clone(),
+            config_dir: self.dir.clone(),
+            languages: self
+                .languages
+                .iter()
+                .map(|(glob, name)| {
+                    let lang = langs
+                        .resolve(name)
+                        .ok_or_else(|| anyhow!("Unknown language in config: {}", name))?;
+                    Ok((glob.clone(), lang))
+                })
+                .collect::<Result<_>>()?,
+            langs,
+            ..defaults
+        })
+    }
+}
+
+impl Thresholds {
+    pub fn is_empty(&self) -> bool {
+        self.max_total_loc.is_none()
+            && self.max_percent.is_empty()
+            && self.max_file_loc.is_none()
+            && self.min_comment_ratio.is_none()
+    }
+
+    /// Overrides the limits with the ones set in `other`.
+    pub fn merge(&mut self, other: Thresholds) {
+        self.max_total_loc = other.max_total_loc.or(self.max_total_loc);
+        self.max_percent.extend(other.max_percent);
+        self.max_file_loc = other.max_file_loc.or(self.max_file_loc);
+        self.min_comment_ratio = other.min_comment_ratio.or(self.min_comment_ratio);
+    }
+
+    /// Describes every limit the stats exceed. Language names are looked up in
+    /// `langs`, the registry the stats were counted with. The limit on the lines
+    /// of a file is only checked if the stats list their files (see
+    /// `GetStatsOptions::files`).
+    pub fn violations(&self, stats: &Stats, langs: &LangRegistry) -> Vec<String> {
+        let mut violations = Vec::new();
+
+        if let Some(max) = self.max_total_loc {
+            if stats.total_loc > max {
+                violations.push(format!(
+                    "Total lines of code: {} exceeds the limit of {}",
+                    stats.total_loc, max
+                ));
+            }
+        }
+
+        for (name, max) in &self.max_percent {
+            let lang = langs.resolve(name).unwrap_or(name);
+            // `LangStat::percent` is rounded down, which could hide a small excess
+            let loc = stats.by_lang.get(lang).map_or(0, |stat| stat.loc);
+            let percent = loc as f32 / stats.total_loc.max(1) as f32 * 100.0;
+            if percent > *max {
+                violations.push(format!(
+                    "{}: {}% of lines exceeds the limit of {}%",
+                    lang,
+                    (percent * 100.0).ceil() / 100.0,
+                    max
+                ));
+            }
+        }
+
+        if let Some(max) = self.max_file_loc {
+            for file in stats.files.iter().filter(|file| file.loc > max) {
+                violations.push(format!(
+                    "{}: {} lines exceeds the limit of {}",
+                    file.path.display(),
+                    file.loc,
+                    max
+                ));
+            }
+        }
+
+        if let Some(min) = self.min_comment_ratio {
+            let (commented, non_blank) =
+                stats
+                    .by_lang
+                    .values()
+                    .fold((0, 0), |(commented, non_blank), stat| {
+                        let documented = stat.comments + stat.docs;
+                        (commented + documented, non_blank + documented + stat.code)
+                    });
+            let ratio = commented as f32 / non_blank.max(1) as f32;
+            if ratio < min {
+                violations.push(format!(
+                    "Comment ratio: {:.2} is below the limit of {}",
+                    ratio, min
+                ));
+            }
+        }
+
+        violations
+    }
+}
+
+impl CustomLanguage {
+    /// The syntax lives as long as the program, like the built-in ones.
+    fn syntax(&self) -> LangSyntax {
+        LangSyntax {
+            line_comments: leak_strs(&self.line_comments),
+            block_comments: leak_pairs(&self.block_comments),
+            nested: self.nested,
+            nested_comments: &[],
+            quotes: leak_pairs(&self.quotes),
+            verbatim_quotes: leak_pairs(&self.verbatim_quotes),
+            char_literals: false,
+            docs: DocSyntax {
+                line_comments: leak_strs(&self.doc_comments),
+                block_comments: leak_pairs(&self.doc_block_comments),
+                ..NO_DOCS
+            },
+        }
+    }
+}
+
+fn leak_str(s: &str) -> &'static str {
+    Box::leak(s.to_string().into_boxed_str())
+}
+
+fn leak_strs(strs: &[String]) -> &'static [&'static str] {
+    Box::leak(strs.iter().map(|s| leak_str(s)).collect())
+}
+
+fn leak_pairs(pairs: &[(String, String)]) -> &'static [(&'static str, &'static str)] {
+    Box::leak(
+        pairs
+            .iter()
+            .map(|(start, end)| (leak_str(start), leak_str(end)))
+            .collect(),
+    )
+}
@@ -1,90 +1,363 @@
-use std::sync::Mutex;
+use std::sync::{LazyLock, Mutex};
 use std::{
-    collections::HashMap,
-    fs::File,
-    io::{BufRead, BufReader},
-    path::Path,
+    borrow::Cow,
+    collections::{BTreeMap, HashMap},
+    fs::{self, File},
+    io::Read,
+    path::{self, Path, PathBuf},
 };
 
-use crate::langs::{LangsMap, LANGS_MAP};
+use crate::gitattributes::{GitAttributes, LinguistAttributes};
+use crate::langs::{
+    LangRegistry, LangSyntax, LangsMap, FILENAMES_MAP, FILENAME_PREFIXES, HEURISTICS_MAP,
+    INTERPRETERS_MAP, LANGS_MAP,
+};
 use anyhow::Result;
-use ignore::WalkBuilder;
-use serde::Serialize;
+use ignore::{
+    overrides::{Override, OverrideBuilder},
+    DirEntry, WalkBuilder,
+};
+use indexmap::IndexMap;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+/// How many bytes from the beginning of a file are inspected by the content heuristics.
+const HEURISTICS_PEEK_LEN: usize = 16 * 1024;
+
+/// How many bytes from the beginning of a file are inspected to tell if it is binary.
+const BINARY_PEEK_LEN: usize = 8 * 1024;
+
+/// How many lines at the beginning and at the end of a file are searched for modelines.
+const MODELINE_SEARCH_LINES: usize = 5;
+
+/// `HEURISTICS_MAP` with the patterns compiled, keyed by extension.
+static HEURISTICS: LazyLock<HashMap<&'static str, Vec<(&'static str, Regex)>>> =
+    LazyLock::new(|| {
+        HEURISTICS_MAP
+            .entries()
+            .map(|(ext, heuristics)| {
+                let compiled = heuristics
+                    .iter()
+                    .map(|heuristic| {
+                        let regex =
+                            Regex::new(heuristic.pattern).expect("invalid heuristic pattern");
+                        (heuristic.lang, regex)
+                    })
+                    .collect();
+                (*ext, compiled)
+            })
+            .collect()
+    });
+
+/// Matches Vim modelines like `vim: set ft=python:`.
+static VIM_MODELINE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?:^|\s)(?:vi|vim|ex)(?:[<=>]?\d+)?:.*?\b(?:ft|filetype|syntax)=([\w+#.-]+)")
+        .unwrap()
+});
+
+/// Matches Emacs modelines like `-*- mode: ruby -*-` or `-*- ruby -*-`.
+static EMACS_MODELINE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)-\*-(?:\s*([\w+#-]+)\s*|.*?\bmode:\s*([\w+#-]+).*?)-\*-").unwrap()
+});
 
 pub struct GetStatsOptions {
     pub gitignore: bool,
+    /// Honors `linguist-*` attributes from `.gitattributes` files.
+    pub linguist: bool,
+    /// Globs of files to count. When empty, all files are counted.
+    pub include: Vec<String>,
+    /// Globs of files and directories to skip.
+    pub exclude: Vec<String>,
+    /// Like `include`, but relative to `config_dir` instead of the counted path.
+    pub config_include: Vec<String>,
+    /// Like `exclude`, but relative to `config_dir` instead of the counted path.
+    pub config_exclude: Vec<String>,
+    /// Directory of the config file the `config_*` and `languages` globs come
+    /// from. Paths outside of it are matched relative to the counted path.
+    pub config_dir: Option<PathBuf>,
+    /// User-defined languages and extension mappings, on top of the built-in ones.
+    pub langs: LangRegistry,
+    /// Globs paired with the language of the files they match, overriding detection.
+    /// The first matching glob wins.
+    pub languages: Vec<(String, &'static str)>,
+    /// Lists every counted file in `Stats::files`.
+    pub files: bool,
+}
+
+impl Default for GetStatsOptions {
+    fn default() -> Self {
+        Self {
+            gitignore: true,
+            linguist: true,
+            include: Vec::new(),
+            exclude: Vec::new(),
+            config_include: Vec::new(),
+            config_exclude: Vec::new(),
+            config_dir: None,
+            langs: LangRegistry::new(),
+            languages: Vec::new(),
+            files: false,
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Serialize)]
 pub struct LangStat {
+    pub files: usize,
     pub loc: usize,
+    pub code: usize,
+    pub comments: usize,
+    pub docs: usize,
+    pub blanks: usize,
+    pub bytes: u64,
+    /// Average lines per file.
+    pub avg_loc: f32,
+    /// Lines of the longest file.
+    pub max_loc: usize,
     pub percent: f32,
 }
 
 impl Default for LangStat {
     fn default() -> Self {
         Self {
+            files: 0,
             loc: 0,
+            code: 0,
+            comments: 0,
+            docs: 0,
+            blanks: 0,
+            bytes: 0,
+            avg_loc: 0.0,
+            max_loc: 0,
             percent: 0.0,
         }
     }
 }
 
+impl LangStat {
+    fn add_file(&mut self, lines: &LineCounts, bytes: u64) {
+        self.files += 1;
+        self.loc += lines.total();
+        self.code += lines.code;
+        self.comments += lines.comments;
+        self.docs += lines.docs;
+        self.blanks += lines.blanks;
+        self.bytes += bytes;
+        self.max_loc = self.max_loc.max(lines.total());
+    }
+
+    fn add(&mut self, other: &LangStat) {
+        self.files += other.files;
+        self.loc += other.loc;
+        self.code += other.code;
+        self.comments += other.comments;
+        self.docs += other.docs;
+        self.blanks += other.blanks;
+        self.bytes += other.bytes;
+        self.max_loc = self.max_loc.max(other.max_loc);
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub(crate) struct LineCounts {
+    code: usize,
+    comments: usize,
+    docs: usize,
+    blanks: usize,
+}
+
+impl LineCounts {
+    fn total(&self) -> usize {
+        self.code + self.comments + self.docs + self.blanks
+    }
+}
+
+/// A block comment that was opened and is not closed yet.
+#[derive(Debug, Clone, Copy)]
+struct OpenComment {
+    start: &'static str,
+    end: &'static str,
+    nestable: bool,
+    depth: usize,
+    doc: bool,
+}
+
+/// A string literal that was opened and is not closed yet.
+#[derive(Debug, Clone, Copy)]
+struct OpenString {
+    end: &'static str,
+    escapes: bool,
+    doc: bool,
+    /// Whether the string can run over several lines. Others are closed at the
+    /// end of their line, so that a stray quote like in `echo Don't` does not
+    /// turn the rest of the file into a string.
+    multiline: bool,
+}
+
+/// Carries an unterminated block comment or string literal over to the next line.
+#[derive(Debug)]
+struct ScanState {
+    comment: Option<OpenComment>,
+    string: Option<OpenString>,
+    /// Whether a docstring may start on the next line, i.e. nothing but
+    /// comments has been seen yet or the last code line ended a `def` or
+    /// `class` header.
+    docstring_allowed: bool,
+    /// Whether the statement being read is a `def` or `class` header.
+    in_header: bool,
+    /// Brackets opened and not closed yet, which continue a statement on the
+    /// next line.
+    brackets: usize,
+}
+
+impl Default for ScanState {
+    fn default() -> Self {
+        Self {
+            comment: None,
+            string: None,
+            docstring_allowed: true,
+            in_header: false,
+            brackets: 0,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum LineKind {
+    Code,
+    Comment,
+    Doc,
+    Blank,
+}
+
+/// Files left out of the totals because of their `linguist-*` attributes.
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct ExcludedStat {
+    pub files: usize,
+    pub loc: usize,
+}
+
+/// Binary files, which are skipped instead of having their lines counted.
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct BinaryStat {
+    pub files: usize,
+    pub bytes: u64,
+}
+
 #[derive(Debug, PartialEq, Serialize)]
 pub struct Stats {
     pub total_loc: usize,
     pub number_of_files: usize,
-    pub by_lang: HashMap<&'static str, LangStat>,
+    /// Ordered as set by `Stats::sort`, by lines by default.
+    pub by_lang: IndexMap<&'static str, LangStat>,
+    /// Vendored, generated and documentation files, keyed by the kind.
+    pub excluded: BTreeMap<&'static str, ExcludedStat>,
+    pub binaries: BinaryStat,
+    /// Every counted file, ordered like `by_lang`. Only filled in when
+    /// `GetStatsOptions::files` is set.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub files: Vec<FileStat>,
+}
+
+/// A single counted file.
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct FileStat {
+    pub path: PathBuf,
+    pub lang: &'static str,
+    pub loc: usize,
+    pub code: usize,
+    pub comments: usize,
+    pub docs: usize,
+    pub blanks: usize,
+    pub bytes: u64,
+}
+
+/// Lines of a directory and everything below it.
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct DirStat {
+    pub loc: usize,
+    pub files: usize,
+    /// The language with the most lines.
+    pub lang: Option<&'static str>,
+    pub by_lang: BTreeMap<&'static str, usize>,
+    /// Subdirectories, keyed by name and ordered by lines by default.
+    #[serde(skip_serializing_if = "IndexMap::is_empty")]
+    pub dirs: IndexMap<String, DirStat>,
+}
+
+/// The order of languages, files and directories in the output.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum SortOrder {
+    /// Most lines first, ties broken by name.
+    #[default]
+    Lines,
+    /// Alphabetically.
+    Name,
+}
+
+/// Stats of one of the paths passed to `get_stats_roots`.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct RootStats {
+    pub path: PathBuf,
+    #[serde(flatten)]
+    pub stats: Stats,
+}
+
+/// Everything needed to detect the language of a file, prepared once per run.
+pub(crate) struct Detector<'a> {
+    pub(crate) attributes: Option<GitAttributes>,
+    overrides: Vec<(Override, &'static str)>,
+    config_base: ConfigBase,
+    langs: &'a LangRegistry,
+}
+
+/// The result of reading and classifying a single file.
+#[derive(Clone, Copy)]
+pub(crate) enum CountedFile {
+    Text {
+        lang: &'static str,
+        lines: LineCounts,
+        bytes: u64,
+        excluded: Option<&'static str>,
+    },
+    Binary {
+        bytes: u64,
+    },
 }
 
 pub fn get_stats_sync(path: &Path, options: &GetStatsOptions) -> Result<Stats> {
     let mut paths = Vec::new();
 
-    let sync_walker = WalkBuilder::new(path).git_ignore(options.gitignore).build();
+    let sync_walker = walk_builder(path, options)?.build();
     for result in sync_walker {
         let entry = result?;
 
         let path = entry.path();
-        if path.is_dir() {
+        if path.is_dir() || is_link(&entry) {
             continue;
         } else {
             paths.push(path.to_path_buf());
         }
     }
 
+    let detector = Detector::new(path, options)?;
     let mut stats = Stats::new();
-    let mut total_loc = 0;
-    let mut total_files = 0;
 
     paths.iter().for_each(|path| {
-        total_files += 1;
-        let loc = count_newlines(path).unwrap_or_default();
-        let lang = get_file_lang(path, &LANGS_MAP).unwrap_or("Other");
-        let entry = stats.by_lang.entry(lang).or_default();
-
-        total_loc += loc;
-        entry.loc += loc;
+        stats.add_file(path, count_file(path, &detector), options.files);
     });
 
-    stats.number_of_files = total_files;
-    stats.total_loc = total_loc;
-
-    for entry in &mut stats.by_lang {
-        entry.1.percent = entry.1.loc as f32 / stats.total_loc as f32 * 100.0;
-        // round down to 2 decimal places
-        entry.1.percent = (entry.1.percent * 100.0).floor() / 100.0;
-    }
+    stats.finish();
 
     Ok(stats)
 }
 
 pub fn get_stats_parallel(path: &Path, options: &GetStatsOptions) -> Result<Stats> {
+    let detector = Detector::new(path, options)?;
     let stats = Mutex::new(Stats::new());
 
-    let walker = WalkBuilder::new(path)
-        .git_ignore(options.gitignore)
-        .threads(6)
-        .build_parallel();
+    let walker = walk_builder(path, options)?.threads(6).build_parallel();
     walker.run(|| {
         Box::new(|result| {
             use ignore::WalkState;
@@ -98,58 +371,780 @@ pub fn get_stats_parallel(path: &Path, options: &GetStatsOptions) -> Result<Stat
             };
 
             let path = entry.path();
-            if path.is_dir() {
+            if path.is_dir() || is_link(&entry) {
                 return WalkState::Continue;
             }
 
-            let loc = count_newlines(path).unwrap_or_default();
-            let lang = get_file_lang(path, &LANGS_MAP).unwrap_or("Other");
-
-            let mut stats = stats.lock().unwrap();
-            stats.total_loc += loc;
-            stats.number_of_files += 1;
-            let entry = stats.by_lang.entry(lang).or_default();
-            entry.loc += loc;
+            let file = count_file(path, &detector);
+            stats.lock().unwrap().add_file(path, file, options.files);
 
             WalkState::Continue
         })
     });
 
     let mut stats = stats.into_inner().unwrap();
+    stats.finish();
+
+    Ok(stats)
+}
 
-    for entry in &mut stats.by_lang {
-        entry.1.percent = entry.1.loc as f32 / stats.total_loc as f32 * 100.0;
-        // round down to 2 decimal places
-        entry.1.percent = (entry.1.percent * 100.0).floor() / 100.0;
+/// Gets the stats of each path, leaving out paths inside another one (or given
+/// twice) so that no file is counted more than once. Use `Stats::combine` to
+/// add them up.
+pub fn get_stats_roots(paths: &[PathBuf], options: &GetStatsOptions) -> Result<Vec<RootStats>> {
+    dedup_roots(paths)
+        .into_iter()
+        .map(|path| {
+            let stats = get_stats_parallel(&path, options)?;
+            Ok(RootStats { path, stats })
+        })
+        .collect()
+}
+
+impl DirStat {
+    /// Rolls the lines of `files` up the directories below `root`. Directories
+    /// deeper than `depth` are counted in their ancestor at that depth, and files
+    /// outside `root` are left out.
+    pub fn from_files(root: &Path, files: &[FileStat], depth: Option<usize>) -> Self {
+        let mut tree = Self::default();
+        for file in files {
+            let Ok(relative) = file.path.strip_prefix(root) else {
+                continue;
+            };
+            let dirs = relative
+                .parent()
+                .into_iter()
+                .flat_map(Path::iter)
+                .take(depth.unwrap_or(usize::MAX));
+
+            let mut dir = &mut tree;
+            dir.add_file(file);
+            for name in dirs {
+                dir = dir
+                    .dirs
+                    .entry(name.to_string_lossy().into_owned())
+                    .or_default();
+                dir.add_file(file);
+            }
+        }
+        tree.find_langs();
+        tree.sort(SortOrder::default());
+        tree
     }
 
-    Ok(stats)
+    pub fn sort(&mut self, order: SortOrder) {
+        match order {
+            SortOrder::Lines => self
+                .dirs
+                .sort_by(|a_name, a, b_name, b| b.loc.cmp(&a.loc).then(a_name.cmp(b_name))),
+            SortOrder::Name => self.dirs.sort_keys(),
+        }
+        for dir in self.dirs.values_mut() {
+            dir.sort(order);
+        }
+    }
+
+    fn add_file(&mut self, file: &FileStat) {
+        self.loc += file.loc;
+        self.files += 1;
+        *self.by_lang.entry(file.lang).or_default() += file.loc;
+    }
+
+    fn find_langs(&mut self) {
+        self.lang = self
+            .by_lang
+            .iter()
+            .max_by_key(|(_, loc)| **loc)
+            .map(|(lang, _)| *lang);
+        self.dirs.values_mut().for_each(Self::find_langs);
+    }
+}
+
+/// Removes the paths that are equal to or inside another path, keeping the order.
+pub fn dedup_roots(paths: &[PathBuf]) -> Vec<PathBuf> {
+    let canonical: Vec<PathBuf> = paths
+        .iter()
+        .map(|path| path.canonicalize().unwrap_or_else(|_| path.clone()))
+        .collect();
+
+    paths
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| {
+            !canonical.iter().enumerate().any(|(j, other)| {
+                // of two equal paths, the first one is kept
+                j != *i && canonical[*i].starts_with(other) && (canonical[*i] != *other || j < *i)
+            })
+        })
+        .map(|(_, path)| path.clone())
+        .collect()
+}
+
+/// Symlinks below the root are skipped, so that their targets are not counted
+/// twice and a git revision, which only has the links, gives the same stats.
+fn is_link(entry: &DirEntry) -> bool {
+    entry.path_is_symlink() && entry.depth() > 0
+}
+
+fn walk_builder(path: &Path, options: &GetStatsOptions) -> Result<WalkBuilder> {
+    let mut builder = WalkBuilder::new(path);
+    builder.git_ignore(options.gitignore);
+
+    // includes are not passed as overrides, because whitelisted files would
+    // bypass .gitignore
+    let globs = Globs::new(path, options)?;
+    if !globs.is_empty() {
+        builder.filter_entry(move |entry| {
+            let is_dir = entry
+                .file_type()
+                .is_some_and(|file_type| file_type.is_dir());
+            !globs.is_excluded(entry.path(), is_dir) && (is_dir || globs.is_included(entry.path()))
+        });
+    }
+
+    Ok(builder)
+}
+
+/// The `include` and `exclude` globs, ready to match the paths below a root.
+pub(crate) struct Globs {
+    excludes: Option<Override>,
+    includes: Option<Override>,
+    config_excludes: Option<Override>,
+    config_includes: Option<Override>,
+    config_base: ConfigBase,
+}
+
+impl Globs {
+    pub(crate) fn new(root: &Path, options: &GetStatsOptions) -> Result<Self> {
+        let config_base = ConfigBase::new(root, options);
+        let excludes = overrides(OverrideBuilder::new(root), &options.exclude, "!")?;
+        let includes = overrides(OverrideBuilder::new(root), &options.include, "")?;
+        let config_excludes = overrides(config_base.builder(), &options.config_exclude, "!")?;
+        let config_includes = overrides(config_base.builder(), &options.config_include, "")?;
+
+        Ok(Self {
+            excludes,
+            includes,
+            config_excludes,
+            config_includes,
+            config_base,
+        })
+    }
+
+    fn is_empty(&self) -> bool {
+        self.excludes.is_none()
+            && self.includes.is_none()
+            && self.config_excludes.is_none()
+            && self.config_includes.is_none()
+    }
+
+    /// Whether the file or directory at `path` matches an `exclude` glob.
+    pub(crate) fn is_excluded(&self, path: &Path, is_dir: bool) -> bool {
+        let excluded =
+            |excludes: &Override, path: &Path| excludes.matched(path, is_dir).is_ignore();
+        self.excludes
+            .as_ref()
+            .is_some_and(|excludes| excluded(excludes, path))
+            || self
+                .config_excludes
+                .as_ref()
+                .is_some_and(|excludes| excluded(excludes, &self.config_base.path(path)))
+    }
+
+    /// Whether the file at `path` matches an `include` glob, if there are any.
+    pub(crate) fn is_included(&self, path: &Path) -> bool {
+        let included =
+            |includes: &Override, path: &Path| includes.matched(path, false).is_whitelist();
+        match (&self.includes, &self.config_includes) {
+            (None, None) => true,
+            (includes, config_includes) => {
+                includes
+                    .as_ref()
+                    .is_some_and(|includes| included(includes, path))
+                    || config_includes
+                        .as_ref()
+                        .is_some_and(|includes| included(includes, &self.config_base.path(path)))
+            }
+        }
+    }
+}
+
+/// Builds the globs, each one prefixed with `prefix`, or `None` if there are none.
+fn overrides(
+    mut builder: OverrideBuilder,
+    globs: &[String],
+    prefix: &str,
+) -> Result<Option<Override>> {
+    if globs.is_empty() {
+        return Ok(None);
+    }
+    for glob in globs {
+        builder.add(&format!("{}{}", prefix, glob))?;
+    }
+    Ok(Some(builder.build()?))
+}
+
+/// Matches the paths below a root against globs of the config file, which
+/// are relative to the directory of the file.
+#[derive(Clone)]
+struct ConfigBase {
+    root: PathBuf,
+    /// Where the root is inside the directory of the config file, if it is.
+    prefix: Option<PathBuf>,
+}
+
+impl ConfigBase {
+    fn new(root: &Path, options: &GetStatsOptions) -> Self {
+        let absolute = |path: &Path| {
+            path.canonicalize()
+                .or_else(|_| path::absolute(path))
+                .unwrap_or_else(|_| path.to_path_buf())
+        };
+        let prefix = options.config_dir.as_deref().and_then(|dir| {
+            let root = absolute(root);
+            Some(root.strip_prefix(absolute(dir)).ok()?.to_path_buf())
+        });
+        Self {
+            root: root.to_path_buf(),
+            prefix,
+        }
+    }
+
+    fn builder(&self) -> OverrideBuilder {
+        match self.prefix {
+            Some(_) => OverrideBuilder::new(""),
+            None => OverrideBuilder::new(&self.root),
+        }
+    }
+
+    /// The path that globs built by `builder` are matched against.
+    fn path<'p>(&self, path: &'p Path) -> Cow<'p, Path> {
+        let relative = self.prefix.as_ref().zip(path.strip_prefix(&self.root).ok());
+        match relative {
+            // a counted file is the root itself
+            Some((prefix, relative)) if relative.as_os_str().is_empty() => {
+                Cow::Owned(prefix.clone())
+            }
+            Some((prefix, relative)) => Cow::Owned(prefix.join(relative)),
+            None => Cow::Borrowed(path),
+        }
+    }
+}
+
+fn count_file(path: &Path, detector: &Detector) -> CountedFile {
+    // only the first block is read until the file is known to be text, so that
+    // large binaries are never loaded whole
+    let mut contents = Vec::new();
+    let mut file = File::open(path).ok();
+    if let Some(file) = &mut file {
+        let _ = file
+            .by_ref()
+            .take(BINARY_PEEK_LEN as u64)
+            .read_to_end(&mut contents);
+    }
+    if is_binary(&contents) {
+        let bytes = fs::metadata(path).map_or(contents.len() as u64, |metadata| metadata.len());
+        return CountedFile::Binary { bytes };
+    }
+
+    if let Some(file) = &mut file {
+        let _ = file.read_to_end(&mut contents);
+    }
+    count_contents(path, &contents, detector)
+}
+
+pub(crate) fn count_contents(path: &Path, contents: &[u8], detector: &Detector) -> CountedFile {
+    if is_binary(contents) {
+        return CountedFile::Binary {
+            bytes: contents.len() as u64,
+        };
+    }
+
+    let linguist = detector
+        .attributes
+        .as_ref()
+        .map(|attributes| attributes.linguist(path))
+        .unwrap_or_default();
+
+    let lang = linguist
+        .language
+        .as_deref()
+        .and_then(|name| detector.langs.resolve(name))
+        .unwrap_or_else(|| detector.detect_lang(path, contents));
+    let lines = count_lines(contents, detector.langs.syntax(lang));
+
+    CountedFile::Text {
+        lang,
+        lines,
+        bytes: contents.len() as u64,
+        excluded: LinguistAttributes::excluded_as(&linguist),
+    }
+}
+
+/// Treats a file as binary if its first block has a NUL byte or isn't valid UTF-8.
+fn is_binary(contents: &[u8]) -> bool {
+    let block = &contents[..contents.len().min(BINARY_PEEK_LEN)];
+    if block.contains(&0) {
+        return true;
+    }
+
+    match std::str::from_utf8(block) {
+        Ok(_) => false,
+        // the block may end in the middle of a multi-byte character
+        Err(err) => err.error_len().is_some(),
+    }
 }
 
 #[inline]
-fn count_newlines(path: &Path) -> Result<usize> {
-    let file = File::open(path)?;
-    let mut reader = BufReader::new(file);
-
-    let mut n = 0;
-    let mut v = Vec::new();
-    let new_line_byte = 0xA;
-    loop {
-        v.clear();
-        let res = reader.read_until(new_line_byte, &mut v);
-        if res.is_err() || res.unwrap() == 0 {
+fn count_lines(contents: &[u8], syntax: Option<&LangSyntax>) -> LineCounts {
+    let mut counts = LineCounts::default();
+    let mut state = ScanState::default();
+    for line in contents.split_inclusive(|&byte| byte == b'\n') {
+        let kind = match syntax {
+            Some(syntax) => classify_line(line, syntax, &mut state),
+            None if line.trim_ascii().is_empty() => LineKind::Blank,
+            None => LineKind::Code,
+        };
+        match kind {
+            LineKind::Code => counts.code += 1,
+            LineKind::Comment => counts.comments += 1,
+            LineKind::Doc => counts.docs += 1,
+            LineKind::Blank => counts.blanks += 1,
+        }
+    }
+
+    counts
+}
+
+/// Statements after which a docstring documents them, e.g. in Python.
+const DOCSTRING_HEADERS: &[&str] = &["def", "class", "async def"];
+
+/// Classifies a single line. `state` holds the block comment or string that is
+/// still open from previous lines, if any, and is updated for the next line.
+fn classify_line(line: &[u8], syntax: &LangSyntax, state: &mut ScanState) -> LineKind {
+    let line = line.trim_ascii();
+    if line.is_empty() {
+        return LineKind::Blank;
+    }
+
+    let mut has_code = false;
+    let mut has_comment = false;
+    let mut has_doc = false;
+    match (&state.comment, &state.string) {
+        (Some(open), _) if open.doc => has_doc = true,
+        (Some(_), _) => has_comment = true,
+        (_, Some(open)) if open.doc => has_doc = true,
+        (_, Some(_)) => has_code = true,
+        (None, None) => {}
+    }
+
+    // everything on a line like `@doc """` belongs to the documentation, but
+    // `@doc false` hides the docs instead
+    let in_doc_attribute = state.comment.is_none()
+        && state.string.is_none()
+        && syntax.docs.attributes.iter().any(|attribute| {
+            starts_with_word(line, attribute) && line[attribute.len()..].trim_ascii() != b"false"
+        });
+    has_doc |= in_doc_attribute;
+
+    let starts_statement = state.brackets == 0 && state.string.is_none();
+    let mut opened_docstring = false;
+    let mut last_code_byte = None;
+    let mut i = 0;
+    while i < line.len() {
+        let rest = &line[i..];
+
+        if let Some(open) = state.string {
+            if open.escapes && rest[0] == b'\\' {
+                i += 2;
+            } else if rest.starts_with(open.end.as_bytes()) {
+                state.string = None;
+                i += open.end.len();
+            } else {
+                i += 1;
+            }
+            continue;
+        }
+
+        if let Some(open) = &mut state.comment {
+            if rest.starts_with(open.end.as_bytes()) {
+                open.depth -= 1;
+                i += open.end.len();
+                if open.depth == 0 {
+                    state.comment = None;
+                }
+            } else if open.nestable && rest.starts_with(open.start.as_bytes()) {
+                open.depth += 1;
+                i += open.start.len();
+            } else {
+                i += 1;
+            }
+            continue;
+        }
+
+        // block markers are checked first, because some of them start with
+        // a line comment marker (e.g. `--[[` in Lua)
+        if let Some((start, open)) = find_block_start(rest, syntax) {
+            if open.doc {
+                has_doc = true;
+            } else {
+                has_comment = true;
+            }
+            i += start.len();
+            state.comment = Some(open);
+            continue;
+        }
+
+        if syntax
+            .docs
+            .line_comments
+            .iter()
+            .any(|marker| rest.starts_with(marker.as_bytes()) && !extends_doc_marker(rest, marker))
+        {
+            has_doc = true;
+            break;
+        }
+
+        if starts_with_any(rest, syntax.line_comments) {
+            has_comment = true;
             break;
         }
-        n += 1;
+
+        if let Some(len) = char_literal_len(rest).filter(|_| syntax.char_literals) {
+            if !in_doc_attribute {
+                has_code = true;
+                last_code_byte = Some(rest[0]);
+            }
+            i += len;
+            continue;
+        }
+
+        let docstring_allowed = i == 0 && state.docstring_allowed;
+        if let Some((start, mut open)) = find_string_start(rest, syntax, docstring_allowed) {
+            opened_docstring |= open.doc;
+            open.doc |= in_doc_attribute;
+            if open.doc {
+                has_doc = true;
+            } else {
+                has_code |= !in_doc_attribute;
+                last_code_byte = Some(rest[0]);
+            }
+            i += start.len();
+            state.string = Some(open);
+            continue;
+        }
+
+        if !rest[0].is_ascii_whitespace() && !in_doc_attribute {
+            has_code = true;
+            last_code_byte = Some(rest[0]);
+            match rest[0] {
+                b'(' | b'[' | b'{' => state.brackets += 1,
+                b')' | b']' | b'}' => state.brackets = state.brackets.saturating_sub(1),
+                _ => {}
+            }
+        }
+        i += 1;
+    }
+
+    // a docstring may only follow the `:` ending a header, which can span lines
+    if let Some(byte) = last_code_byte.filter(|_| !syntax.docs.docstrings.is_empty()) {
+        if starts_statement {
+            state.in_header = DOCSTRING_HEADERS
+                .iter()
+                .any(|header| starts_with_word(line, header));
+        }
+        state.docstring_allowed = state.in_header && state.brackets == 0 && byte == b':';
+        if state.brackets == 0 {
+            state.in_header = false;
+        }
     }
+    if opened_docstring {
+        state.docstring_allowed = false;
+    }
+    if state.string.is_some_and(|open| !open.multiline) {
+        state.string = None;
+    }
+
+    if has_code {
+        LineKind::Code
+    } else if has_doc {
+        LineKind::Doc
+    } else if has_comment {
+        LineKind::Comment
+    } else {
+        LineKind::Blank
+    }
+}
+
+fn starts_with_any(rest: &[u8], markers: &[&str]) -> bool {
+    markers
+        .iter()
+        .any(|marker| rest.starts_with(marker.as_bytes()))
+}
+
+/// Checks that `line` starts with `word` followed by a non-identifier character.
+fn starts_with_word(line: &[u8], word: &str) -> bool {
+    line.starts_with(word.as_bytes())
+        && line
+            .get(word.len())
+            .is_none_or(|byte| !byte.is_ascii_alphanumeric() && *byte != b'_')
+}
 
-    Ok(n)
+/// Whether the doc marker at the start of `rest` is followed by one more `/` or
+/// `*`, like `////` or `/***`, which make a regular comment instead.
+fn extends_doc_marker(rest: &[u8], marker: &str) -> bool {
+    let last = marker.as_bytes().last();
+    matches!(last, Some(b'/' | b'*')) && rest.get(marker.len()) == last
+}
+
+fn find_block_start(rest: &[u8], syntax: &LangSyntax) -> Option<(&'static str, OpenComment)> {
+    let nested = syntax.nested_comments.iter().map(|pair| (pair, true));
+    let plain = syntax
+        .block_comments
+        .iter()
+        .map(|pair| (pair, syntax.nested));
+
+    let (&(start, end), nestable) = nested
+        .chain(plain)
+        .find(|((start, _), _)| rest.starts_with(start.as_bytes()))?;
+
+    // doc comments extend the regular markers (`/**` vs `/*`), but an empty
+    // comment like `/**/` is not a doc comment
+    let is_empty = rest[start.len()..].starts_with(end.as_bytes());
+    let doc_start = syntax
+        .docs
+        .block_comments
+        .iter()
+        .filter(|_| !is_empty)
+        .find(|(doc_start, doc_end)| {
+            *doc_end == end
+                && rest.starts_with(doc_start.as_bytes())
+                && !extends_doc_marker(rest, doc_start)
+        })
+        .map(|(doc_start, _)| *doc_start);
+
+    let open = OpenComment {
+        start,
+        end,
+        nestable,
+        depth: 1,
+        doc: doc_start.is_some(),
+    };
+    Some((doc_start.unwrap_or(start), open))
+}
+
+/// Length of the character literal at the start of `rest`, like `'a'`, `'\''`
+/// or `'\u{1F600}'`. `None` if there is none, e.g. for a lifetime like `'a`.
+fn char_literal_len(rest: &[u8]) -> Option<usize> {
+    if rest.first() != Some(&b'\'') {
+        return None;
+    }
+
+    if rest.get(1) == Some(&b'\\') {
+        // the escaped character is never the closing quote
+        let close = 3 + rest
+            .get(3..)?
+            .iter()
+            .take(10)
+            .position(|&byte| byte == b'\'')?;
+        return Some(close + 1);
+    }
+
+    let width = match *rest.get(1)? {
+        byte if byte < 0x80 => 1,
+        byte if byte >= 0xF0 => 4,
+        byte if byte >= 0xE0 => 3,
+        _ => 2,
+    };
+    (rest.get(1 + width) == Some(&b'\'')).then_some(width + 2)
+}
+
+fn find_string_start(
+    rest: &[u8],
+    syntax: &LangSyntax,
+    docstring_allowed: bool,
+) -> Option<(&'static str, OpenString)> {
+    let docstrings = syntax
+        .docs
+        .docstrings
+        .iter()
+        .filter(|_| docstring_allowed)
+        .map(|pair| (pair, true, true));
+    let verbatim = syntax
+        .verbatim_quotes
+        .iter()
+        .map(|pair| (pair, false, false));
+    let escaped = syntax.quotes.iter().map(|pair| (pair, true, false));
+
+    docstrings
+        .chain(verbatim)
+        .chain(escaped)
+        .find(|((start, _), _, _)| rest.starts_with(start.as_bytes()))
+        .map(|(&(start, end), escapes, doc)| {
+            // triple quotes, raw strings and template literals span lines, while
+            // plain quotes are mostly closed on the same line
+            let multiline = start.len() > 1 || start == "`";
+            let open = OpenString {
+                end,
+                escapes,
+                doc,
+                multiline,
+            };
+            (start, open)
+        })
+}
+
+impl<'a> Detector<'a> {
+    pub(crate) fn new(root: &Path, options: &'a GetStatsOptions) -> Result<Self> {
+        let config_base = ConfigBase::new(root, options);
+        let overrides = options
+            .languages
+            .iter()
+            .map(|(glob, lang)| {
+                let mut builder = config_base.builder();
+                builder.add(glob)?;
+                Ok((builder.build()?, *lang))
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            attributes: options.linguist.then(|| GitAttributes::new(root)),
+            overrides,
+            config_base,
+            langs: &options.langs,
+        })
+    }
+
+    #[inline]
+    fn detect_lang(&self, path: &Path, contents: &[u8]) -> &'static str {
+        if let Some((_, lang)) = self.overrides.iter().find(|(glob, _)| {
+            glob.matched(self.config_base.path(path), false)
+                .is_whitelist()
+        }) {
+            return lang;
+        }
+
+        get_modeline_lang(contents, self.langs)
+            .or_else(|| get_file_lang(path, contents, self.langs, &FILENAMES_MAP, &LANGS_MAP))
+            .or_else(|| get_shebang_lang(contents, &INTERPRETERS_MAP))
+            .unwrap_or("Other")
+    }
 }
 
 #[inline]
-fn get_file_lang(path: &Path, langs_map: &LangsMap) -> Option<&'static str> {
-    let ext = path.extension()?;
-    Some(langs_map.get(ext.to_str().unwrap_or_default())?)
+fn get_file_lang(
+    path: &Path,
+    contents: &[u8],
+    registry: &LangRegistry,
+    filenames_map: &LangsMap,
+    langs_map: &LangsMap,
+) -> Option<&'static str> {
+    let file_name = path.file_name()?.to_str().unwrap_or_default();
+    if let Some(lang) = registry.filename_lang(file_name) {
+        return Some(lang);
+    }
+    if let Some(lang) = filenames_map.get(file_name) {
+        return Some(lang);
+    }
+    if let Some((_, lang)) = FILENAME_PREFIXES
+        .iter()
+        .find(|(prefix, _)| file_name.starts_with(prefix))
+    {
+        return Some(lang);
+    }
+
+    let ext = path.extension()?.to_str().unwrap_or_default();
+    if let Some(lang) = registry.extension_lang(ext) {
+        return Some(lang);
+    }
+    let heuristics = registry
+        .heuristics(ext)
+        .into_iter()
+        .chain(HEURISTICS.get(ext).map(Vec::as_slice));
+    for heuristics in heuristics {
+        if let Some(lang) = disambiguate(contents, heuristics) {
+            return Some(lang);
+        }
+    }
+    Some(langs_map.get(ext)?)
+}
+
+/// Chooses between the languages sharing an extension based on the file contents.
+fn disambiguate(contents: &[u8], heuristics: &[(&'static str, Regex)]) -> Option<&'static str> {
+    let contents = &contents[..contents.len().min(HEURISTICS_PEEK_LEN)];
+    let contents = String::from_utf8_lossy(contents);
+
+    heuristics
+        .iter()
+        .find(|(_, regex)| regex.is_match(&contents))
+        .map(|(lang, _)| *lang)
+}
+
+fn get_shebang_lang(contents: &[u8], interpreters_map: &LangsMap) -> Option<&'static str> {
+    let first_line = contents.split(|&byte| byte == b'\n').next()?;
+    let first_line = &first_line[..first_line.len().min(256)];
+
+    let interpreter = parse_shebang(std::str::from_utf8(first_line).ok()?)?;
+    // names with a version, like `python3.11`, fall back to the bare name
+    let unversioned = interpreter.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    interpreters_map
+        .get(interpreter)
+        .or_else(|| interpreters_map.get(unversioned))
+        .copied()
+}
+
+/// Extracts the interpreter name from a shebang line, e.g. `python3.11` from
+/// `#!/usr/bin/env -S python3.11 -u`.
+fn parse_shebang(line: &str) -> Option<&str> {
+    let mut words = line.strip_prefix("#!")?.split_whitespace();
+    let program = basename(words.next()?);
+    if program != "env" {
+        return Some(program);
+    }
+
+    // skip env's options (like `-S`), along with the values of `-u` and `-C`,
+    // and variable assignments
+    while let Some(word) = words.next() {
+        match word {
+            "-u" | "-C" | "--unset" | "--chdir" => {
+                words.next();
+            }
+            _ if word.starts_with('-') || word.contains('=') => {}
+            _ => return Some(basename(word)),
+        }
+    }
+    None
+}
+
+/// Looks for a Vim or Emacs modeline in the first and last few lines of the file.
+fn get_modeline_lang(contents: &[u8], registry: &LangRegistry) -> Option<&'static str> {
+    // only the ends of the file are scanned, as it can be large
+    let head_len = contents
+        .iter()
+        .enumerate()
+        .filter(|(_, &byte)| byte == b'\n')
+        .nth(MODELINE_SEARCH_LINES - 1)
+        .map_or(contents.len(), |(i, _)| i + 1);
+    let (head, tail) = contents.split_at(head_len);
+    let head = head
+        .split(|&byte| byte == b'\n')
+        .take(MODELINE_SEARCH_LINES);
+    let tail = tail
+        .rsplit(|&byte| byte == b'\n')
+        .take(MODELINE_SEARCH_LINES);
+
+    head.chain(tail).find_map(|line| {
+        let line = String::from_utf8_lossy(line);
+        let captures = VIM_MODELINE
+            .captures(&line)
+            .or_else(|| EMACS_MODELINE.captures(&line))?;
+        let name = captures.iter().skip(1).flatten().next()?.as_str();
+        registry.resolve(name)
+    })
+}
+
+fn basename(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
 }
 
 impl Stats {
@@ -157,7 +1152,107 @@ impl Stats {
         Self {
             total_loc: 0,
             number_of_files: 0,
-            by_lang: HashMap::new(),
+            by_lang: IndexMap::new(),
+            excluded: BTreeMap::new(),
+            binaries: BinaryStat::default(),
+            files: Vec::new(),
+        }
+    }
+
+    pub(crate) fn add_file(&mut self, path: &Path, file: CountedFile, keep_file: bool) {
+        let (lang, lines, bytes, excluded) = match file {
+            CountedFile::Text {
+                lang,
+                lines,
+                bytes,
+                excluded,
+            } => (lang, lines, bytes, excluded),
+            CountedFile::Binary { bytes } => {
+                self.binaries.files += 1;
+                self.binaries.bytes += bytes;
+                return;
+            }
+        };
+
+        if let Some(kind) = excluded {
+            let entry = self.excluded.entry(kind).or_default();
+            entry.files += 1;
+            entry.loc += lines.total();
+            return;
+        }
+
+        self.total_loc += lines.total();
+        self.number_of_files += 1;
+        self.by_lang
+            .entry(lang)
+            .or_default()
+            .add_file(&lines, bytes);
+
+        if keep_file {
+            self.files.push(FileStat {
+                path: path.to_path_buf(),
+                lang,
+                loc: lines.total(),
+                code: lines.code,
+                comments: lines.comments,
+                docs: lines.docs,
+                blanks: lines.blanks,
+                bytes,
+            });
+        }
+    }
+
+    /// Adds up the stats of several paths.
+    pub fn combine<'a>(all: impl IntoIterator<Item = &'a Stats>) -> Self {
+        let mut combined = Self::new();
+        for stats in all {
+            combined.total_loc += stats.total_loc;
+            combined.number_of_files += stats.number_of_files;
+            for (lang, stat) in &stats.by_lang {
+                combined.by_lang.entry(lang).or_default().add(stat);
+            }
+            for (kind, stat) in &stats.excluded {
+                let entry = combined.excluded.entry(kind).or_default();
+                entry.files += stat.files;
+                entry.loc += stat.loc;
+            }
+            combined.binaries.files += stats.binaries.files;
+            combined.binaries.bytes += stats.binaries.bytes;
+            combined.files.extend(stats.files.iter().cloned());
+        }
+        combined.finish();
+        combined
+    }
+
+    /// Orders the languages and files, which are otherwise in the order they were counted in.
+    pub fn sort(&mut self, order: SortOrder) {
+        match order {
+            SortOrder::Lines => {
+                self.by_lang
+                    .sort_by(|a_name, a, b_name, b| b.loc.cmp(&a.loc).then(a_name.cmp(b_name)));
+                self.files
+                    .sort_by(|a, b| b.loc.cmp(&a.loc).then_with(|| a.path.cmp(&b.path)));
+            }
+            SortOrder::Name => {
+                self.by_lang.sort_keys();
+                self.files.sort_by(|a, b| a.path.cmp(&b.path));
+            }
+        }
+    }
+
+    pub(crate) fn finish(&mut self) {
+        self.sort(SortOrder::default());
+        self.compute_ratios();
+    }
+
+    fn compute_ratios(&mut self) {
+        for entry in &mut self.by_lang {
+            entry.1.percent = entry.1.loc as f32 / self.total_loc as f32 * 100.0;
+            // round down to 2 decimal places
+            entry.1.percent = (entry.1.percent * 100.0).floor() / 100.0;
+
+            entry.1.avg_loc = entry.1.loc as f32 / entry.1.files.max(1) as f32;
+            entry.1.avg_loc = (entry.1.avg_loc * 100.0).round() / 100.0;
         }
     }
 }
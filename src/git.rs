@@ -0,0 +1,574 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io::{BufRead, BufReader, Read, Write},
+    path::{Component, Path, PathBuf},
+    process::{Command, Stdio},
+    thread,
+};
+
+use anyhow::{bail, Context, Result};
+use ignore::{
+    gitignore::{Gitignore, GitignoreBuilder},
+    Match,
+};
+use serde::Serialize;
+
+use crate::get_stats::{
+    count_contents, dedup_roots, CountedFile, Detector, GetStatsOptions, Globs, RootStats, Stats,
+};
+use crate::gitattributes::GitAttributes;
+
+/// Which commits of a branch `Repo::commits` picks.
+#[derive(Debug, Default, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum Sampling {
+    /// Every commit, following the first parent of merges.
+    #[default]
+    Commit,
+    /// The last commit of each day.
+    Day,
+    /// The last commit of each week, starting on Monday.
+    Week,
+    /// Every tagged commit.
+    Tag,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Commit {
+    #[serde(rename = "commit")]
+    pub id: String,
+    /// Committer date, in ISO 8601 format.
+    pub date: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+}
+
+/// Stats of the files of a commit.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct CommitStats {
+    #[serde(flatten)]
+    pub commit: Commit,
+    #[serde(flatten)]
+    pub stats: Stats,
+}
+
+/// A git repository, read by running the `git` command.
+pub struct Repo {
+    /// The top-level directory of the working tree.
+    top: PathBuf,
+}
+
+/// A file in the tree of a commit.
+struct TreeEntry {
+    /// Relative to the top of the repository, separated by `/`.
+    path: String,
+    id: String,
+}
+
+/// Counts the files of some paths as they are in any commit, reading them from
+/// the git object database instead of the working tree. Files that did not
+/// change since a previously counted commit are not read again.
+pub struct TreeCounter<'a> {
+    repo: Repo,
+    options: &'a GetStatsOptions,
+    /// Each path paired with the directory it stands for, relative to the top
+    /// of the repository.
+    roots: Vec<(PathBuf, PathBuf)>,
+    /// Counted files by path and blob id.
+    cache: HashMap<(String, String), CountedFile>,
+    /// Ids of the `.gitattributes` files the cached counts were made with.
+    attributes_ids: Vec<String>,
+    /// `.git/info/exclude` and the global excludes file, which apply to every commit.
+    excludes: (Gitignore, Gitignore),
+}
+
+/// Gets the stats of each path as of `rev`, a branch, tag or other commit-ish,
+/// as they would be in a clean checkout of it.
+pub fn get_stats_rev(
+    paths: &[PathBuf],
+    rev: &str,
+    options: &GetStatsOptions,
+) -> Result<Vec<RootStats>> {
+    let mut counter = TreeCounter::new(paths, options)?;
+    let commit = counter.repo().resolve(rev)?;
+    counter.count(&commit)
+}
+
+impl Repo {
+    /// Finds the repository containing `path`, which does not have to exist in
+    /// the working tree.
+    pub fn discover(path: &Path) -> Result<Self> {
+        let (top, _) = locate(path)?;
+        Ok(Self { top })
+    }
+
+    /// Resolves a branch, tag or other commit-ish to a commit id.
+    pub fn resolve(&self, rev: &str) -> Result<String> {
+        let commit = format!("{}^{{commit}}", rev);
+        let id = self
+            .git(&["rev-parse", "--verify", "--end-of-options", &commit])
+            .with_context(|| format!("Unknown revision: {}", rev))?;
+        Ok(id.trim_end().to_string())
+    }
+
+    /// Lists the commits of `branch`, oldest first. `since` is a date in any
+    /// format git understands, e.g. `2024-01-31` or `2 years ago`.
+    pub fn commits(
+        &self,
+        branch: &str,
+        sampling: Sampling,
+        since: Option<&str>,
+    ) -> Result<Vec<Commit>> {
+        let mut args = vec![
+            "log",
+            "--format=%H%x09%cI%x09%D",
+            "--decorate-refs=refs/tags/",
+        ];
+        // tags on merged branches are left out by --first-parent
+        if sampling != Sampling::Tag {
+            args.push("--first-parent");
+        }
+        let since = since.map(|since| format!("--since={}", since));
+        args.extend(since.as_deref());
+        args.extend(["--end-of-options", branch, "--"]);
+
+        let mut commits: Vec<Commit> = self
+            .git(&args)?
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.splitn(3, '\t');
+                let id = fields.next()?.to_string();
+                let date = fields.next()?.to_string();
+                let tags = fields
+                    .next()
+                    .unwrap_or_default()
+                    .split(", ")
+                    .filter_map(|name| name.strip_prefix("tag: "))
+                    .map(str::to_string)
+                    .collect();
+                Some(Commit { id, date, tags })
+            })
+            .collect();
+
+        // the newest commits come first, so the last commit of a period is seen first
+        let mut periods = HashSet::new();
+        commits.retain(|commit| match sampling {
+            Sampling::Commit => true,
+            Sampling::Day => periods.insert(day_number(&commit.date)),
+            Sampling::Week => {
+                periods.insert(day_number(&commit.date).map(|day| (day + 3).div_euclid(7)))
+            }
+            Sampling::Tag => !commit.tags.is_empty(),
+        });
+        commits.reverse();
+        Ok(commits)
+    }
+
+    /// Where `path` is inside the repository, relative to its top.
+    fn dir_of(&self, path: &Path) -> Result<PathBuf> {
+        let (top, dir) = locate(path)?;
+        if top != self.top {
+            bail!(
+                "{} is not inside the repository at {}",
+                path.display(),
+                self.top.display()
+            );
+        }
+        Ok(dir)
+    }
+
+    /// Reads `.git/info/exclude`, whose patterns are relative to the top.
+    fn info_exclude(&self) -> Gitignore {
+        let Ok(path) = self.git(&["rev-parse", "--git-path", "info/exclude"]) else {
+            return Gitignore::empty();
+        };
+        let mut builder = GitignoreBuilder::new(&self.top);
+        builder.add(self.top.join(path.trim_end()));
+        builder.build().unwrap_or_else(|_| Gitignore::empty())
+    }
+
+    /// Lists the files of a commit, leaving out symlinks and submodules like a
+    /// walk of the working tree does.
+    fn tree(&self, commit: &str) -> Result<Vec<TreeEntry>> {
+        let output = self.git(&["ls-tree", "-r", "-z", "--full-tree", commit])?;
+        Ok(output
+            .split('\0')
+            .filter_map(|line| {
+                let (info, path) = line.split_once('\t')?;
+                let mut info = info.split(' ');
+                let (mode, kind, id) = (info.next()?, info.next()?, info.next()?);
+                (kind == "blob" && mode != "120000").then(|| TreeEntry {
+                    path: path.to_string(),
+                    id: id.to_string(),
+                })
+            })
+            .collect())
+    }
+
+    /// Reads the contents of blobs, passing each one to `each` in the order of `ids`.
+    fn read_blobs(&self, ids: &[&str], mut each: impl FnMut(usize, Vec<u8>)) -> Result<()> {
+        if ids.is_empty() {
+            return Ok(());
+        }
+
+        let mut child = self
+            .command(&["cat-file", "--batch"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .context("Could not run git")?;
+        let mut stdin = child.stdin.take().context("Could not write to git")?;
+        let input: String = ids.iter().map(|id| format!("{}\n", id)).collect();
+        // written from another thread, so that git never blocks on a full stdout
+        let writer = thread::spawn(move || stdin.write_all(input.as_bytes()));
+
+        let mut stdout = BufReader::new(child.stdout.take().context("Could not read from git")?);
+        for (i, id) in ids.iter().enumerate() {
+            // each blob is preceded by `<id> blob <size>` and followed by a newline
+            let mut header = String::new();
+            stdout.read_line(&mut header)?;
+            let size: usize = header
+                .trim_end()
+                .rsplit(' ')
+                .next()
+                .and_then(|size| size.parse().ok())
+                .with_context(|| format!("Could not read object {}", id))?;
+            let mut contents = vec![0; size + 1];
+            stdout.read_exact(&mut contents)?;
+            contents.pop();
+            each(i, contents);
+        }
+
+        writer.join().expect("writer thread panicked")?;
+        child.wait()?;
+        Ok(())
+    }
+
+    fn git(&self, args: &[&str]) -> Result<String> {
+        let output = self.command(args).output().context("Could not run git")?;
+        if !output.status.success() {
+            bail!(
+                "git {} failed: {}",
+                args[0],
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    fn command(&self, args: &[&str]) -> Command {
+        let mut command = Command::new("git");
+        command.arg("-C").arg(&self.top).args(args);
+        command
+    }
+}
+
+impl<'a> TreeCounter<'a> {
+    /// Prepares to count `paths`, which have to be inside the same repository.
+    pub fn new(paths: &[PathBuf], options: &'a GetStatsOptions) -> Result<Self> {
+        let first = paths.first().context("No paths to count")?;
+        let repo = Repo::discover(first)?;
+        let roots = dedup_roots(paths)
+            .into_iter()
+            .map(|path| {
+                let dir = repo.dir_of(&path)?;
+                Ok((path, dir))
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            excludes: (repo.info_exclude(), Gitignore::global().0),
+            repo,
+            options,
+            roots,
+            cache: HashMap::new(),
+            attributes_ids: Vec::new(),
+        })
+    }
+
+    pub fn repo(&self) -> &Repo {
+        &self.repo
+    }
+
+    /// Gets the stats of each path as of `commit`, like `get_stats_roots` does
+    /// for the working tree.
+    pub fn count(&mut self, commit: &str) -> Result<Vec<RootStats>> {
+        let tree = self.repo.tree(commit)?;
+
+        let attributes = if self.options.linguist {
+            self.read_all(&tree, ".gitattributes")?
+        } else {
+            Vec::new()
+        };
+        let attributes_ids: Vec<String> = attributes.iter().map(|(id, ..)| id.clone()).collect();
+        if attributes_ids != self.attributes_ids {
+            self.cache.clear();
+            self.attributes_ids = attributes_ids;
+        }
+        let attributes: Vec<(PathBuf, String)> = attributes
+            .into_iter()
+            .map(|(_, dir, contents)| (dir, contents))
+            .collect();
+
+        // like a walk, `.ignore` files are honored even when `.gitignore` files are not
+        let gitignores = if self.options.gitignore {
+            self.read_all(&tree, ".gitignore")?
+        } else {
+            Vec::new()
+        };
+        let ignores = TreeIgnores {
+            top: &self.repo.top,
+            ignores: matchers(self.read_all(&tree, ".ignore")?),
+            gitignores: matchers(gitignores),
+            excludes: &self.excludes,
+        };
+
+        let mut all = Vec::new();
+        for (root, dir) in &self.roots {
+            let mut detector = Detector::new(root, self.options)?;
+            detector.attributes = self.options.linguist.then(|| {
+                GitAttributes::from_files(
+                    root,
+                    self.repo.top.join(dir),
+                    self.repo.top.clone(),
+                    attributes.iter().cloned(),
+                )
+            });
+            let filter = TreeFilter::new(root, self.repo.top.join(dir), self.options, &ignores)?;
+
+            let files: Vec<(&TreeEntry, PathBuf)> = tree
+                .iter()
+                .filter_map(|entry| {
+                    let relative = Path::new(&entry.path).strip_prefix(dir).ok()?;
+                    let path = if relative.as_os_str().is_empty() {
+                        root.clone()
+                    } else {
+                        root.join(relative)
+                    };
+                    filter.is_counted(relative).then_some((entry, path))
+                })
+                .collect();
+
+            let missing: Vec<&(&TreeEntry, PathBuf)> = files
+                .iter()
+                .filter(|(entry, _)| !self.cache.contains_key(&cache_key(entry)))
+                .collect();
+            let ids: Vec<&str> = missing.iter().map(|(entry, _)| entry.id.as_str()).collect();
+            let cache = &mut self.cache;
+            self.repo.read_blobs(&ids, |i, contents| {
+                let (entry, path) = missing[i];
+                cache.insert(cache_key(entry), count_contents(path, &contents, &detector));
+            })?;
+
+            let mut stats = Stats::new();
+            for (entry, path) in &files {
+                stats.add_file(path, self.cache[&cache_key(entry)], self.options.files);
+            }
+            stats.finish();
+            all.push(RootStats {
+                path: root.clone(),
+                stats,
+            });
+        }
+        Ok(all)
+    }
+
+    /// Reads every file of the tree with the given name, returning its id, its
+    /// directory and its contents.
+    fn read_all(&self, tree: &[TreeEntry], name: &str) -> Result<Vec<(String, PathBuf, String)>> {
+        let entries: Vec<&TreeEntry> = tree
+            .iter()
+            .filter(|entry| entry.path.rsplit('/').next() == Some(name))
+            .collect();
+        let ids: Vec<&str> = entries.iter().map(|entry| entry.id.as_str()).collect();
+
+        let mut files = Vec::new();
+        self.repo.read_blobs(&ids, |i, contents| {
+            let entry = entries[i];
+            let dir = Path::new(&entry.path).parent().unwrap_or(Path::new(""));
+            files.push((
+                entry.id.clone(),
+                self.repo.top.join(dir),
+                String::from_utf8_lossy(&contents).into_owned(),
+            ));
+        })?;
+        Ok(files)
+    }
+}
+
+/// The ignore files of a tree, looked at in the same order as a walk of the
+/// working tree does.
+struct TreeIgnores<'a> {
+    top: &'a Path,
+    /// `.ignore` files, keyed by their directory.
+    ignores: HashMap<PathBuf, Gitignore>,
+    /// `.gitignore` files, keyed by their directory.
+    gitignores: HashMap<PathBuf, Gitignore>,
+    /// `.git/info/exclude` and the global excludes file.
+    excludes: &'a (Gitignore, Gitignore),
+}
+
+impl TreeIgnores<'_> {
+    /// Whether the file or directory at `path`, inside `top`, is ignored.
+    /// `shown` is the same path as it is shown, which global patterns are
+    /// matched against.
+    fn is_ignored(&self, path: &Path, shown: &Path, is_dir: bool) -> bool {
+        let (info_exclude, global) = self.excludes;
+        self.matched(&self.ignores, path, is_dir)
+            .or(self.matched(&self.gitignores, path, is_dir))
+            .or(info_exclude.matched(path, is_dir))
+            .or(global.matched(shown, is_dir))
+            .is_ignore()
+    }
+
+    /// Deeper files take precedence, so they are looked at first.
+    fn matched<'m>(
+        &self,
+        matchers: &'m HashMap<PathBuf, Gitignore>,
+        path: &Path,
+        is_dir: bool,
+    ) -> Match<&'m ignore::gitignore::Glob> {
+        path.ancestors()
+            .skip(1)
+            .take_while(|dir| dir.starts_with(self.top))
+            .filter_map(|dir| matchers.get(dir))
+            .map(|matcher| matcher.matched(path, is_dir))
+            .find(|matched| !matched.is_none())
+            .unwrap_or(Match::None)
+    }
+}
+
+/// Builds a matcher for each ignore file read by `TreeCounter::read_all`.
+fn matchers(files: Vec<(String, PathBuf, String)>) -> HashMap<PathBuf, Gitignore> {
+    files
+        .into_iter()
+        .filter_map(|(_, dir, contents)| {
+            let mut builder = GitignoreBuilder::new(&dir);
+            for line in contents.lines() {
+                // invalid patterns are skipped, like git does
+                let _ = builder.add_line(None, line);
+            }
+            Some((dir, builder.build().ok()?))
+        })
+        .collect()
+}
+
+/// Picks the files of a tree that a walk of the same files on disk would visit.
+struct TreeFilter<'a> {
+    root: &'a Path,
+    /// The directory `root` stands for, inside the top of the repository.
+    dir: PathBuf,
+    globs: Globs,
+    ignores: &'a TreeIgnores<'a>,
+}
+
+impl<'a> TreeFilter<'a> {
+    fn new(
+        root: &'a Path,
+        dir: PathBuf,
+        options: &GetStatsOptions,
+        ignores: &'a TreeIgnores<'a>,
+    ) -> Result<Self> {
+        Ok(Self {
+            root,
+            dir,
+            globs: Globs::new(root, options)?,
+            ignores,
+        })
+    }
+
+    /// Whether the file at `relative` below the root is counted.
+    fn is_counted(&self, relative: &Path) -> bool {
+        // a path to a single file is always counted
+        if relative.as_os_str().is_empty() {
+            return true;
+        }
+        // hidden files and directories are skipped
+        if relative
+            .iter()
+            .any(|name| name.to_string_lossy().starts_with('.'))
+        {
+            return false;
+        }
+
+        // nothing inside a skipped directory is visited
+        let skipped_dir = relative
+            .ancestors()
+            .skip(1)
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .any(|dir| self.is_skipped(dir, true));
+        if skipped_dir || self.is_skipped(relative, false) {
+            return false;
+        }
+        self.globs.is_included(&self.root.join(relative))
+    }
+
+    fn is_skipped(&self, relative: &Path, is_dir: bool) -> bool {
+        self.globs.is_excluded(&self.root.join(relative), is_dir)
+            || self
+                .ignores
+                .is_ignored(&self.dir.join(relative), &self.root.join(relative), is_dir)
+    }
+}
+
+/// Finds the top of the repository containing `path`, and where `path` is
+/// inside it. Only the nearest existing directory of `path` is looked up on
+/// disk and the rest is resolved lexically, so that paths which were removed
+/// from the working tree can still be counted in older commits.
+fn locate(path: &Path) -> Result<(PathBuf, PathBuf)> {
+    let mut base = path;
+    let mut rest = Vec::new();
+    while !base.is_dir() {
+        match (base.parent(), base.components().next_back()) {
+            (Some(parent), Some(last)) => {
+                rest.push(last);
+                base = parent;
+            }
+            _ => break,
+        }
+    }
+    let base = if base.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        base
+    };
+
+    let repo = Repo {
+        top: base.to_path_buf(),
+    };
+    let output = repo.git(&["rev-parse", "--show-toplevel", "--show-prefix"])?;
+    let mut lines = output.lines();
+    let top = PathBuf::from(lines.next().context("git rev-parse printed nothing")?);
+    let mut dir = PathBuf::from(lines.next().unwrap_or_default().trim_end_matches('/'));
+    for component in rest.into_iter().rev() {
+        match component {
+            Component::Normal(name) => dir.push(name),
+            Component::ParentDir if !dir.pop() => bail!(
+                "{} is not inside the repository at {}",
+                path.display(),
+                top.display()
+            ),
+            _ => {}
+        }
+    }
+    Ok((top.canonicalize().unwrap_or(top), dir))
+}
+
+fn cache_key(entry: &TreeEntry) -> (String, String) {
+    (entry.path.clone(), entry.id.clone())
+}
+
+/// Days since 1970-01-01 of the day an ISO 8601 date starts with.
+fn day_number(date: &str) -> Option<i64> {
+    let mut parts = date.get(..10)?.split('-');
+    let year: i64 = parts.next()?.parse().ok()?;
+    let month: i64 = parts.next()?.parse().ok()?;
+    let day: i64 = parts.next()?.parse().ok()?;
+
+    // see http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    Some(era * 146097 + day_of_era - 719468)
+}
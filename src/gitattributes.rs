@@ -0,0 +1,207 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+
+/// The `linguist-*` attributes that apply to a file, as set in `.gitattributes`.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct LinguistAttributes {
+    /// Value of `linguist-language`.
+    pub language: Option<String>,
+    pub vendored: bool,
+    pub generated: bool,
+    pub documentation: bool,
+}
+
+impl LinguistAttributes {
+    /// Name of the bucket a file is moved to instead of being counted,
+    /// if it is vendored, generated or documentation.
+    pub fn excluded_as(&self) -> Option<&'static str> {
+        if self.vendored {
+            Some("vendored")
+        } else if self.generated {
+            Some("generated")
+        } else if self.documentation {
+            Some("documentation")
+        } else {
+            None
+        }
+    }
+}
+
+/// Changes to the attributes made by a single `.gitattributes` line.
+/// `None` leaves the attribute as set by the previous lines.
+#[derive(Debug, Default)]
+struct AttributeChanges {
+    language: Option<String>,
+    vendored: Option<bool>,
+    generated: Option<bool>,
+    documentation: Option<bool>,
+}
+
+struct Rule {
+    matcher: Gitignore,
+    changes: AttributeChanges,
+}
+
+/// Resolves `linguist-*` attributes for files under `root`, reading the
+/// `.gitattributes` files of every directory between the repository root and
+/// the file. Parsed files are cached, so it can be shared between threads.
+pub struct GitAttributes {
+    root: PathBuf,
+    canonical_root: PathBuf,
+    /// The top-most directory whose `.gitattributes` applies, i.e. the root
+    /// of the git repository containing `root`, or `root` itself.
+    top: PathBuf,
+    cache: Mutex<HashMap<PathBuf, Arc<Vec<Rule>>>>,
+    /// Whether `.gitattributes` files missing from the cache are read from disk.
+    read_files: bool,
+}
+
+impl GitAttributes {
+    pub fn new(root: &Path) -> Self {
+        let canonical_root = root.canonicalize().unwrap_or_else(|_| root.to_path_buf());
+        let top = canonical_root
+            .ancestors()
+            .find(|dir| dir.join(".git").exists())
+            .unwrap_or(&canonical_root)
+            .to_path_buf();
+
+        Self {
+            root: root.to_path_buf(),
+            canonical_root,
+            top,
+            cache: Mutex::new(HashMap::new()),
+            read_files: true,
+        }
+    }
+
+    /// Resolves attributes from the contents of `.gitattributes` files that were
+    /// read elsewhere, e.g. from a git tree, keyed by their directory. Files are
+    /// given below `root`, which stands for the directory `dir` inside `top`.
+    pub fn from_files(
+        root: &Path,
+        dir: PathBuf,
+        top: PathBuf,
+        files: impl IntoIterator<Item = (PathBuf, String)>,
+    ) -> Self {
+        let cache = files
+            .into_iter()
+            .map(|(dir, contents)| {
+                let rules = Arc::new(parse(&dir, &contents));
+                (dir, rules)
+            })
+            .collect();
+
+        Self {
+            root: root.to_path_buf(),
+            canonical_root: dir,
+            top,
+            cache: Mutex::new(cache),
+            read_files: false,
+        }
+    }
+
+    pub fn linguist(&self, path: &Path) -> LinguistAttributes {
+        let path = match path.strip_prefix(&self.root) {
+            Ok(relative) if relative.as_os_str().is_empty() => self.canonical_root.clone(),
+            Ok(relative) => self.canonical_root.join(relative),
+            Err(_) => return LinguistAttributes::default(),
+        };
+
+        let mut dirs: Vec<&Path> = path
+            .ancestors()
+            .skip(1)
+            .take_while(|dir| dir.starts_with(&self.top))
+            .collect();
+        // deeper files take precedence, so they are applied last
+        dirs.reverse();
+
+        let mut attributes = LinguistAttributes::default();
+        for dir in dirs {
+            for rule in self.rules(dir).iter() {
+                if rule.matcher.matched(&path, false).is_ignore() {
+                    rule.changes.apply(&mut attributes);
+                }
+            }
+        }
+        attributes
+    }
+
+    fn rules(&self, dir: &Path) -> Arc<Vec<Rule>> {
+        if let Some(rules) = self.cache.lock().unwrap().get(dir) {
+            return rules.clone();
+        }
+        if !self.read_files {
+            return Arc::default();
+        }
+
+        let rules = Arc::new(
+            fs::read_to_string(dir.join(".gitattributes"))
+                .map(|contents| parse(dir, &contents))
+                .unwrap_or_default(),
+        );
+        self.cache
+            .lock()
+            .unwrap()
+            .insert(dir.to_path_buf(), rules.clone());
+        rules
+    }
+}
+
+impl AttributeChanges {
+    fn apply(&self, attributes: &mut LinguistAttributes) {
+        if let Some(language) = &self.language {
+            attributes.language = Some(language.clone());
+        }
+        if let Some(vendored) = self.vendored {
+            attributes.vendored = vendored;
+        }
+        if let Some(generated) = self.generated {
+            attributes.generated = generated;
+        }
+        if let Some(documentation) = self.documentation {
+            attributes.documentation = documentation;
+        }
+    }
+}
+
+fn parse(dir: &Path, contents: &str) -> Vec<Rule> {
+    contents
+        .lines()
+        .filter_map(|line| {
+            let mut words = line.split_whitespace();
+            let pattern = words.next().filter(|word| !word.starts_with('#'))?;
+
+            let mut changes = AttributeChanges::default();
+            for word in words {
+                let (name, value) = match word.split_once('=') {
+                    Some((name, value)) => (name, Some(value)),
+                    None => (word, None),
+                };
+                let (name, enabled) = match name.strip_prefix(['-', '!']) {
+                    Some(name) => (name, false),
+                    None => (name, value != Some("false")),
+                };
+
+                match name {
+                    "linguist-language" => changes.language = value.map(str::to_string),
+                    "linguist-vendored" => changes.vendored = Some(enabled),
+                    "linguist-generated" => changes.generated = Some(enabled),
+                    "linguist-documentation" => changes.documentation = Some(enabled),
+                    _ => {}
+                }
+            }
+
+            let mut builder = GitignoreBuilder::new(dir);
+            builder.add_line(None, pattern).ok()?;
+            let matcher = builder.build().ok()?;
+
+            Some(Rule { matcher, changes })
+        })
+        .collect()
+}
@@ -0,0 +1,336 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    f32::consts::PI,
+    fmt::Write,
+};
+
+use crate::get_stats::{DirStat, LangStat, Stats};
+
+/// Colors given to languages, by share of lines. Languages past the end of the
+/// list are grouped together and drawn in `REST_COLOR`.
+pub(crate) const PALETTE: &[&str] = &[
+    "#4e79a7", "#f28e2b", "#e15759", "#76b7b2", "#59a14f", "#edc948", "#b07aa1", "#ff9da7",
+    "#9c755f",
+];
+pub(crate) const REST_COLOR: &str = "#bab0ac";
+
+const PIE_RADIUS: f32 = 90.0;
+const TREEMAP_WIDTH: f32 = 960.0;
+const TREEMAP_HEIGHT: f32 = 480.0;
+/// Height of the directory name above its subdirectories in the treemap.
+const TREEMAP_LABEL_HEIGHT: f32 = 14.0;
+
+const STYLE: &str = r#"
+body { font-family: -apple-system, "Segoe UI", Helvetica, Arial, sans-serif; margin: 2em auto; max-width: 1000px; color: #24292f; }
+h1 { font-size: 1.6em; }
+h2 { font-size: 1.2em; margin-top: 2em; }
+table { border-collapse: collapse; width: 100%; font-size: 0.9em; }
+th, td { padding: 4px 8px; border-bottom: 1px solid #d0d7de; text-align: left; }
+td.num, th.num { text-align: right; font-variant-numeric: tabular-nums; }
+th[data-col] { cursor: pointer; user-select: none; }
+th[data-order="asc"]::after { content: " \25B2"; }
+th[data-order="desc"]::after { content: " \25BC"; }
+.swatch { display: inline-block; width: 0.8em; height: 0.8em; margin-right: 0.4em; border-radius: 2px; }
+.languages { display: flex; gap: 2em; align-items: center; }
+.treemap text { font-size: 11px; fill: #fff; pointer-events: none; }
+"#;
+
+/// Sorts the per-file table by the clicked column, toggling the order on each click.
+const SCRIPT: &str = r#"
+document.querySelectorAll("th[data-col]").forEach(function (th) {
+  th.addEventListener("click", function () {
+    var body = th.closest("table").tBodies[0];
+    var col = Number(th.dataset.col);
+    var numeric = th.classList.contains("num");
+    var asc = th.dataset.order !== "asc";
+    th.parentNode.querySelectorAll("th").forEach(function (other) { delete other.dataset.order; });
+    th.dataset.order = asc ? "asc" : "desc";
+    var rows = Array.prototype.slice.call(body.rows).sort(function (a, b) {
+      var x = a.cells[col].textContent, y = b.cells[col].textContent;
+      var order = numeric ? x - y : x.localeCompare(y);
+      return asc ? order : -order;
+    });
+    rows.forEach(function (row) { body.appendChild(row); });
+  });
+});
+"#;
+
+#[derive(Clone, Copy)]
+struct Rect {
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+}
+
+impl Stats {
+    /// Renders a single HTML page, with no external assets, showing a pie chart
+    /// of the languages, a sortable table of `files` and a treemap of `dirs`
+    /// (keyed by the name of each root).
+    pub fn to_html(&self, dirs: &BTreeMap<String, DirStat>) -> String {
+        let colors: HashMap<&str, &str> = self
+            .ranked_langs()
+            .into_iter()
+            .zip(PALETTE)
+            .map(|((lang, _), color)| (lang, *color))
+            .collect();
+        let color_of = |lang: &str| colors.get(lang).copied().unwrap_or(REST_COLOR);
+
+        let mut html = String::new();
+        html.push_str("<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n");
+        html.push_str("<title>loc-stats report</title>\n");
+        let _ = writeln!(html, "<style>{}</style>\n</head>\n<body>", STYLE);
+        let _ = writeln!(
+            html,
+            "<h1>loc-stats report</h1>\n<p><strong>{}</strong> lines of code in <strong>{}</strong> files</p>",
+            self.total_loc, self.number_of_files
+        );
+
+        html.push_str("<h2>Languages</h2>\n<div class=\"languages\">\n");
+        self.pie_chart(&mut html);
+        html.push_str("<table>\n<thead><tr><th>Language</th><th class=\"num\">Files</th><th class=\"num\">Lines</th><th class=\"num\">Code</th><th class=\"num\">Comments</th><th class=\"num\">Docs</th><th class=\"num\">Blanks</th><th class=\"num\">%</th></tr></thead>\n<tbody>\n");
+        for (lang, stat) in &self.by_lang {
+            let _ = writeln!(
+                html,
+                "<tr><td><span class=\"swatch\" style=\"background:{}\"></span>{}</td><td class=\"num\">{}</td><td class=\"num\">{}</td><td class=\"num\">{}</td><td class=\"num\">{}</td><td class=\"num\">{}</td><td class=\"num\">{}</td><td class=\"num\">{}</td></tr>",
+                color_of(lang),
+                escape(lang),
+                stat.files,
+                stat.loc,
+                stat.code,
+                stat.comments,
+                stat.docs,
+                stat.blanks,
+                stat.percent
+            );
+        }
+        html.push_str("</tbody>\n</table>\n</div>\n");
+
+        if !dirs.is_empty() {
+            html.push_str("<h2>Directories</h2>\n");
+            let _ = writeln!(
+                html,
+                "<svg class=\"treemap\" viewBox=\"0 0 {w} {h}\" width=\"100%\" role=\"img\">",
+                w = TREEMAP_WIDTH,
+                h = TREEMAP_HEIGHT
+            );
+            let bounds = Rect {
+                x: 0.0,
+                y: 0.0,
+                width: TREEMAP_WIDTH,
+                height: TREEMAP_HEIGHT,
+            };
+            let total = dirs.values().map(|dir| dir.loc).sum();
+            treemap_children(
+                &mut html,
+                "",
+                dirs.iter().collect(),
+                total,
+                bounds,
+                &color_of,
+            );
+            html.push_str("</svg>\n");
+        }
+
+        if !self.files.is_empty() {
+            html.push_str("<h2>Files</h2>\n<table>\n<thead><tr><th data-col=\"0\">Path</th><th data-col=\"1\">Language</th><th class=\"num\" data-col=\"2\">Lines</th><th class=\"num\" data-col=\"3\">Code</th><th class=\"num\" data-col=\"4\">Comments</th><th class=\"num\" data-col=\"5\">Docs</th><th class=\"num\" data-col=\"6\">Blanks</th><th class=\"num\" data-col=\"7\">Bytes</th></tr></thead>\n<tbody>\n");
+            for file in &self.files {
+                let _ = writeln!(
+                    html,
+                    "<tr><td>{}</td><td>{}</td><td class=\"num\">{}</td><td class=\"num\">{}</td><td class=\"num\">{}</td><td class=\"num\">{}</td><td class=\"num\">{}</td><td class=\"num\">{}</td></tr>",
+                    escape(&file.path.display().to_string()),
+                    escape(file.lang),
+                    file.loc,
+                    file.code,
+                    file.comments,
+                    file.docs,
+                    file.blanks,
+                    file.bytes
+                );
+            }
+            html.push_str("</tbody>\n</table>\n");
+            let _ = writeln!(html, "<script>{}</script>", SCRIPT);
+        }
+
+        html.push_str("</body>\n</html>\n");
+        html
+    }
+
+    /// The languages by share of lines, the biggest first, whatever the order of
+    /// `by_lang`. Languages with as many lines are ordered by name.
+    pub(crate) fn ranked_langs(&self) -> Vec<(&'static str, &LangStat)> {
+        let mut ranked: Vec<_> = self
+            .by_lang
+            .iter()
+            .map(|(lang, stat)| (*lang, stat))
+            .collect();
+        ranked.sort_by(|(a, a_stat), (b, b_stat)| b_stat.loc.cmp(&a_stat.loc).then(a.cmp(b)));
+        ranked
+    }
+
+    fn pie_chart(&self, html: &mut String) {
+        let size = PIE_RADIUS * 2.0 + 20.0;
+        let _ = writeln!(
+            html,
+            "<svg class=\"pie\" viewBox=\"0 0 {s} {s}\" width=\"{s}\" height=\"{s}\" role=\"img\">",
+            s = size
+        );
+
+        let center = size / 2.0;
+        let ranked = self.ranked_langs();
+        let rest: usize = ranked
+            .iter()
+            .skip(PALETTE.len())
+            .map(|(_, stat)| stat.loc)
+            .sum();
+        let slices = ranked
+            .iter()
+            .zip(PALETTE)
+            .map(|((lang, stat), color)| (lang.to_string(), stat.loc, *color))
+            .chain((rest > 0).then(|| ("Other languages".to_string(), rest, REST_COLOR)));
+
+        let mut angle = 0.0f32;
+        for (name, loc, color) in slices {
+            let share = loc as f32 / self.total_loc.max(1) as f32;
+            let title = format!("<title>{}: {}</title>", escape(&name), loc);
+            // an arc can't start and end at the same point, so a full pie is a circle
+            if share >= 0.9999 {
+                let _ = writeln!(
+                    html,
+                    "<circle cx=\"{c}\" cy=\"{c}\" r=\"{r}\" fill=\"{}\">{}</circle>",
+                    color,
+                    title,
+                    c = center,
+                    r = PIE_RADIUS
+                );
+                break;
+            }
+
+            let end = angle + share * 2.0 * PI;
+            let point = |angle: f32| {
+                (
+                    center + PIE_RADIUS * angle.sin(),
+                    center - PIE_RADIUS * angle.cos(),
+                )
+            };
+            let (x0, y0) = point(angle);
+            let (x1, y1) = point(end);
+            let _ = writeln!(
+                html,
+                "<path d=\"M{c},{c} L{:.2},{:.2} A{r},{r} 0 {},1 {:.2},{:.2} Z\" fill=\"{}\">{}</path>",
+                x0,
+                y0,
+                u8::from(share > 0.5),
+                x1,
+                y1,
+                color,
+                title,
+                c = center,
+                r = PIE_RADIUS
+            );
+            angle = end;
+        }
+        html.push_str("</svg>\n");
+    }
+}
+
+/// Lays out `dirs` side by side inside `bounds`, each taking a share of the area
+/// proportional to its lines out of `total`, along the longer side of `bounds`.
+/// Whatever is left over stands for the files directly in the parent directory.
+fn treemap_children(
+    html: &mut String,
+    parent: &str,
+    mut dirs: Vec<(&String, &DirStat)>,
+    total: usize,
+    bounds: Rect,
+    color_of: &dyn Fn(&str) -> &'static str,
+) {
+    dirs.sort_by_key(|entry| std::cmp::Reverse(entry.1.loc));
+
+    let horizontal = bounds.width >= bounds.height;
+    let mut offset = 0.0;
+    for (name, dir) in dirs {
+        let share = dir.loc as f32 / total.max(1) as f32;
+        let rect = if horizontal {
+            Rect {
+                x: bounds.x + offset,
+                width: bounds.width * share,
+                ..bounds
+            }
+        } else {
+            Rect {
+                y: bounds.y + offset,
+                height: bounds.height * share,
+                ..bounds
+            }
+        };
+        offset += if horizontal { rect.width } else { rect.height };
+
+        let path = if parent.is_empty() {
+            name.clone()
+        } else {
+            format!("{}/{}", parent, name)
+        };
+        treemap_dir(html, &path, name, dir, rect, color_of);
+    }
+}
+
+fn treemap_dir(
+    html: &mut String,
+    path: &str,
+    name: &str,
+    dir: &DirStat,
+    rect: Rect,
+    color_of: &dyn Fn(&str) -> &'static str,
+) {
+    if rect.width < 1.0 || rect.height < 1.0 {
+        return;
+    }
+
+    let _ = writeln!(
+        html,
+        "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" fill=\"{}\" stroke=\"#fff\"><title>{}: {} lines, {} files</title></rect>",
+        rect.x,
+        rect.y,
+        rect.width,
+        rect.height,
+        dir.lang.map_or(REST_COLOR, color_of),
+        escape(path),
+        dir.loc,
+        dir.files
+    );
+
+    let has_room = rect.width > 40.0 && rect.height > TREEMAP_LABEL_HEIGHT * 2.0;
+    if !has_room {
+        return;
+    }
+    let _ = writeln!(
+        html,
+        "<text x=\"{:.1}\" y=\"{:.1}\">{}</text>",
+        rect.x + 3.0,
+        rect.y + TREEMAP_LABEL_HEIGHT - 3.0,
+        escape(name)
+    );
+
+    let inner = Rect {
+        x: rect.x + 2.0,
+        y: rect.y + TREEMAP_LABEL_HEIGHT,
+        width: rect.width - 4.0,
+        height: rect.height - TREEMAP_LABEL_HEIGHT - 2.0,
+    };
+    treemap_children(
+        html,
+        path,
+        dir.dirs.iter().collect(),
+        dir.loc,
+        inner,
+        color_of,
+    );
+}
+
+pub(crate) fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
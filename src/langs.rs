@@ -1,4 +1,8 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Context, Result};
 use phf::phf_map;
+use regex::Regex;
 
 pub type LangsMap = phf::Map<&'static str, &'static str>;
 
@@ -904,3 +908,1036 @@ pub static LANGS_MAP: LangsMap = phf_map! {
         "prg" => "xBase",
         "prw" => "xBase",
 };
+
+/// Languages of files that are recognized by their whole name rather than the extension.
+/// Consulted before `LANGS_MAP`.
+pub static FILENAMES_MAP: LangsMap = phf_map! {
+        ".bash_aliases" => "Shell",
+        ".bash_logout" => "Shell",
+        ".bash_profile" => "Shell",
+        ".bashrc" => "Shell",
+        ".cshrc" => "Tcsh",
+        ".editorconfig" => "INI",
+        ".emacs" => "Emacs Lisp",
+        ".gitconfig" => "INI",
+        ".profile" => "Shell",
+        ".vimrc" => "VimL",
+        ".zlogin" => "Shell",
+        ".zprofile" => "Shell",
+        ".zshenv" => "Shell",
+        ".zshrc" => "Shell",
+        "BSDmakefile" => "Makefile",
+        "BUCK" => "Python",
+        "BUILD" => "Python",
+        "BUILD.bazel" => "Python",
+        "Brewfile" => "Ruby",
+        "CMakeLists.txt" => "CMake",
+        "Cargo.lock" => "TOML",
+        "Containerfile" => "Dockerfile",
+        "Dockerfile" => "Dockerfile",
+        "GNUmakefile" => "Makefile",
+        "Gemfile" => "Ruby",
+        "Gemfile.lock" => "Text",
+        "Guardfile" => "Ruby",
+        "Jenkinsfile" => "Groovy",
+        "Justfile" => "Just",
+        "Kbuild" => "Makefile",
+        "Makefile" => "Makefile",
+        "Pipfile" => "TOML",
+        "Podfile" => "Ruby",
+        "Rakefile" => "Ruby",
+        "SConscript" => "Python",
+        "SConstruct" => "Python",
+        "Vagrantfile" => "Ruby",
+        "WORKSPACE" => "Python",
+        "WORKSPACE.bazel" => "Python",
+        "justfile" => "Just",
+        "makefile" => "Makefile",
+        "meson.build" => "Meson",
+        "meson_options.txt" => "Meson",
+};
+
+/// Filename prefixes of files like `Dockerfile.dev` or `Makefile.am`, checked
+/// after `FILENAMES_MAP` and before `LANGS_MAP`.
+pub static FILENAME_PREFIXES: &[(&str, &str)] = &[
+    ("Containerfile.", "Dockerfile"),
+    ("Dockerfile.", "Dockerfile"),
+    ("Jenkinsfile.", "Groovy"),
+    ("Makefile.", "Makefile"),
+];
+
+/// Alternative names of languages used in Vim and Emacs modelines, keyed in lowercase.
+/// Names matching a language (ignoring case) don't need to be listed here.
+pub static LANG_ALIASES: LangsMap = phf_map! {
+        "bash" => "Shell",
+        "c++" => "C++",
+        "cperl" => "Perl",
+        "cpp" => "C++",
+        "cs" => "C#",
+        "csharp" => "C#",
+        "elisp" => "Emacs Lisp",
+        "fsharp" => "F#",
+        "js" => "JavaScript",
+        "js2" => "JavaScript",
+        "ksh" => "Shell",
+        "latex" => "TeX",
+        "lisp" => "Common Lisp",
+        "make" => "Makefile",
+        "md" => "Markdown",
+        "objc" => "Objective-C",
+        "plaintex" => "TeX",
+        "py" => "Python",
+        "python3" => "Python",
+        "rb" => "Ruby",
+        "rs" => "Rust",
+        "sh" => "Shell",
+        "shell-script" => "Shell",
+        "tex" => "TeX",
+        "ts" => "TypeScript",
+        "vim" => "VimL",
+        "yml" => "YAML",
+        "zsh" => "Shell",
+};
+
+/// Picks `lang` for a file whose contents match the regex `pattern`.
+pub struct Heuristic {
+    pub lang: &'static str,
+    pub pattern: &'static str,
+}
+
+pub type HeuristicsMap = phf::Map<&'static str, &'static [Heuristic]>;
+
+/// Extensions shared by several languages. Their heuristics are tried in order
+/// against the beginning of the file, and the first match wins. An empty pattern
+/// always matches. If nothing matches, the language from `LANGS_MAP` is used.
+pub static HEURISTICS_MAP: HeuristicsMap = phf_map! {
+        "d" => &[
+            Heuristic {
+                lang: "D",
+                pattern: r"(?m)^\s*(module|import)\s+[\w.]+\s*;|\bvoid\s+main\s*\(",
+            },
+            Heuristic {
+                lang: "DTrace",
+                pattern: r"(?m)^\s*(syscall|proc|pid\d*|fbt|profile|dtrace)::",
+            },
+        ],
+        "h" => &[
+            Heuristic {
+                lang: "Objective-C",
+                pattern: r"(?m)^\s*(@interface|@implementation|@protocol|@end\b|#import\s)",
+            },
+            Heuristic {
+                lang: "C++",
+                pattern: r"(?m)^\s*(class\s+\w+\s*[:{]|namespace\s+\w*\s*\{|template\s*<)|std::|#include\s*<(iostream|string|vector|memory|map)>",
+            },
+            Heuristic {
+                lang: "C",
+                pattern: "",
+            },
+        ],
+        "inc" => &[
+            Heuristic {
+                lang: "PHP",
+                pattern: r"<\?php",
+            },
+            Heuristic {
+                lang: "SourcePawn",
+                pattern: r"(?m)^\s*(#pragma\s+semicolon|public\s+(Plugin|Action|void)\b|native\s|forward\s)",
+            },
+            Heuristic {
+                lang: "Assembly",
+                pattern: r"(?mi)^\s*(%macro|\.macro|section\s+\.|mov\s)",
+            },
+            Heuristic {
+                lang: "C",
+                pattern: r"(?m)^\s*#\s*(include|define|ifn?def)\b",
+            },
+        ],
+        "m" => &[
+            Heuristic {
+                lang: "Objective-C",
+                pattern: r"(?m)^\s*(@interface|@implementation|@protocol|@end\b|#import\s|#include\s)",
+            },
+            Heuristic {
+                lang: "Matlab",
+                pattern: r"(?m)^\s*(function\b|%|end\s*$)",
+            },
+        ],
+        "mod" => &[
+            Heuristic {
+                lang: "XML",
+                pattern: r"^\s*<",
+            },
+            Heuristic {
+                lang: "Go Module",
+                pattern: r"(?m)^(module|go|require)\s",
+            },
+            Heuristic {
+                lang: "Modula-2",
+                pattern: r"(?m)^\s*(IMPLEMENTATION\s+|DEFINITION\s+)?MODULE\s+\w+\s*;",
+            },
+            Heuristic {
+                lang: "AMPL",
+                pattern: r"(?m)^\s*(param|var|set|subject\s+to|minimize|maximize)\b",
+            },
+        ],
+        "pl" => &[
+            Heuristic {
+                lang: "Perl",
+                pattern: r"(?m)^\s*(use\s+(strict|warnings|v?5)|my\s+[$@%]|sub\s+\w+\s*\{|package\s+[\w:]+;)|^#!.*perl",
+            },
+            Heuristic {
+                lang: "Prolog",
+                pattern: r":-",
+            },
+        ],
+        "r" => &[
+            Heuristic {
+                lang: "Rebol",
+                pattern: r"(?i)\brebol\s*\[",
+            },
+            Heuristic {
+                lang: "R",
+                pattern: "",
+            },
+        ],
+};
+
+/// Languages of scripts keyed by the interpreter named in their shebang line,
+/// with any version suffix (`python3.11` -> `python`) stripped.
+pub static INTERPRETERS_MAP: LangsMap = phf_map! {
+        "Rscript" => "R",
+        "ash" => "Shell",
+        "awk" => "Awk",
+        "bash" => "Shell",
+        "bun" => "JavaScript",
+        "crystal" => "Crystal",
+        "csh" => "Tcsh",
+        "dart" => "Dart",
+        "dash" => "Shell",
+        "deno" => "TypeScript",
+        "elixir" => "Elixir",
+        "escript" => "Erlang",
+        "fish" => "fish",
+        "gawk" => "Awk",
+        "groovy" => "Groovy",
+        "guile" => "Scheme",
+        "julia" => "Julia",
+        "ksh" => "Shell",
+        "lua" => "Lua",
+        "luajit" => "Lua",
+        "make" => "Makefile",
+        "mawk" => "Awk",
+        "node" => "JavaScript",
+        "nodejs" => "JavaScript",
+        "ocaml" => "OCaml",
+        "osascript" => "AppleScript",
+        "perl" => "Perl",
+        "perl6" => "Perl6",
+        "php" => "PHP",
+        "pwsh" => "PowerShell",
+        "pypy" => "Python",
+        "python" => "Python",
+        "racket" => "Racket",
+        "raku" => "Perl6",
+        "ruby" => "Ruby",
+        "runghc" => "Haskell",
+        "runhaskell" => "Haskell",
+        "rust-script" => "Rust",
+        "sbcl" => "Common Lisp",
+        "scala" => "Scala",
+        "sh" => "Shell",
+        "swift" => "Swift",
+        "tclsh" => "Tcl",
+        "tcsh" => "Tcsh",
+        "ts-node" => "TypeScript",
+        "wish" => "Tcl",
+        "zsh" => "Shell",
+};
+
+/// Describes how comments are written in a given language.
+pub struct LangSyntax {
+    /// Markers that start a comment running until the end of the line, e.g. `//`.
+    pub line_comments: &'static [&'static str],
+    /// Pairs of markers that open and close a block comment, e.g. `/*` and `*/`.
+    pub block_comments: &'static [(&'static str, &'static str)],
+    /// Whether the `block_comments` can be nested inside each other.
+    pub nested: bool,
+    /// Block comments that always nest, regardless of `nested` (e.g. `/+ +/` in D).
+    pub nested_comments: &'static [(&'static str, &'static str)],
+    /// String delimiters in which a backslash escapes the next character.
+    /// Checked in order, so longer delimiters (e.g. `"""`) must come first.
+    /// Only strings opened by a backtick or a delimiter longer than one
+    /// character run over several lines, the others end with their line.
+    pub quotes: &'static [(&'static str, &'static str)],
+    /// String delimiters without escapes, e.g. raw strings like `r#"..."#`.
+    /// Checked before `quotes`.
+    pub verbatim_quotes: &'static [(&'static str, &'static str)],
+    /// Whether `'` starts a character literal like `'"'` or `'\''`, as opposed to
+    /// a string. A `'` with no closing quote after one character, like in the
+    /// lifetime `'a`, is left alone.
+    pub char_literals: bool,
+    pub docs: DocSyntax,
+}
+
+/// Describes how documentation is written in a given language. Lines matching
+/// these are counted as docs rather than comments or code.
+pub struct DocSyntax {
+    /// Markers of doc comments running until the end of the line, e.g. `///`.
+    /// Checked before the regular line comments.
+    pub line_comments: &'static [&'static str],
+    /// Doc block comments, e.g. `/**` and `*/`. Each one has to extend the start
+    /// of a regular block comment with the same end marker.
+    pub block_comments: &'static [(&'static str, &'static str)],
+    /// String delimiters that form a docstring when they open the first statement
+    /// of a file, or the first statement after a `def` or `class` header (as in Python).
+    pub docstrings: &'static [(&'static str, &'static str)],
+    /// Attributes whose arguments are documentation, e.g. `@doc` in Elixir.
+    pub attributes: &'static [&'static str],
+}
+
+pub const NO_DOCS: DocSyntax = DocSyntax {
+    line_comments: &[],
+    block_comments: &[],
+    docstrings: &[],
+    attributes: &[],
+};
+
+const JAVADOC: DocSyntax = DocSyntax {
+    line_comments: &[],
+    block_comments: &[("/**", "*/")],
+    docstrings: &[],
+    attributes: &[],
+};
+
+const DOXYGEN: DocSyntax = DocSyntax {
+    line_comments: &["///", "//!"],
+    block_comments: &[("/**", "*/"), ("/*!", "*/")],
+    docstrings: &[],
+    attributes: &[],
+};
+
+const TRIPLE_SLASH: DocSyntax = DocSyntax {
+    line_comments: &["///"],
+    block_comments: &[("/**", "*/")],
+    docstrings: &[],
+    attributes: &[],
+};
+
+pub type SyntaxMap = phf::Map<&'static str, LangSyntax>;
+
+const C_STYLE: LangSyntax = LangSyntax {
+    line_comments: &["//"],
+    block_comments: &[("/*", "*/")],
+    nested: false,
+    nested_comments: &[],
+    quotes: &[("\"", "\""), ("'", "'")],
+    verbatim_quotes: &[],
+    char_literals: false,
+    docs: DOXYGEN,
+};
+
+const C_STYLE_NESTED: LangSyntax = LangSyntax {
+    line_comments: &["//"],
+    block_comments: &[("/*", "*/")],
+    nested: true,
+    nested_comments: &[],
+    quotes: &[
+        ("\"\"\"", "\"\"\""),
+        ("'''", "'''"),
+        ("\"", "\""),
+        ("'", "'"),
+    ],
+    verbatim_quotes: &[],
+    char_literals: false,
+    docs: TRIPLE_SLASH,
+};
+
+const RUST: LangSyntax = LangSyntax {
+    line_comments: &["//"],
+    block_comments: &[("/*", "*/")],
+    nested: true,
+    nested_comments: &[],
+    quotes: &[("\"", "\"")],
+    verbatim_quotes: &[
+        ("r###\"", "\"###"),
+        ("r##\"", "\"##"),
+        ("r#\"", "\"#"),
+        ("r\"", "\""),
+    ],
+    char_literals: true,
+    docs: DocSyntax {
+        line_comments: &["///", "//!"],
+        block_comments: &[("/**", "*/"), ("/*!", "*/")],
+        docstrings: &[],
+        attributes: &[],
+    },
+};
+
+const JAVASCRIPT: LangSyntax = LangSyntax {
+    line_comments: &["//"],
+    block_comments: &[("/*", "*/")],
+    nested: false,
+    nested_comments: &[],
+    quotes: &[("\"", "\""), ("'", "'"), ("`", "`")],
+    verbatim_quotes: &[],
+    char_literals: false,
+    docs: JAVADOC,
+};
+
+const GO: LangSyntax = LangSyntax {
+    line_comments: &["//"],
+    block_comments: &[("/*", "*/")],
+    nested: false,
+    nested_comments: &[],
+    quotes: &[("\"", "\""), ("'", "'")],
+    verbatim_quotes: &[("`", "`")],
+    char_literals: false,
+    docs: NO_DOCS,
+};
+
+const CSHARP: LangSyntax = LangSyntax {
+    line_comments: &["//"],
+    block_comments: &[("/*", "*/")],
+    nested: false,
+    nested_comments: &[],
+    quotes: &[("\"", "\""), ("'", "'")],
+    verbatim_quotes: &[("@\"", "\"")],
+    char_literals: false,
+    docs: TRIPLE_SLASH,
+};
+
+/// Verilog uses `'` in sized literals (`8'hFF`), so it can't be a string delimiter.
+const VERILOG: LangSyntax = LangSyntax {
+    line_comments: &["//"],
+    block_comments: &[("/*", "*/")],
+    nested: false,
+    nested_comments: &[],
+    quotes: &[("\"", "\"")],
+    verbatim_quotes: &[],
+    char_literals: false,
+    docs: NO_DOCS,
+};
+
+const C_BLOCK_ONLY: LangSyntax = LangSyntax {
+    line_comments: &[],
+    block_comments: &[("/*", "*/")],
+    nested: false,
+    nested_comments: &[],
+    quotes: &[],
+    verbatim_quotes: &[],
+    char_literals: false,
+    docs: NO_DOCS,
+};
+
+const HASH: LangSyntax = LangSyntax {
+    line_comments: &["#"],
+    block_comments: &[],
+    nested: false,
+    nested_comments: &[],
+    quotes: &[],
+    verbatim_quotes: &[],
+    char_literals: false,
+    docs: NO_DOCS,
+};
+
+const PYTHON: LangSyntax = LangSyntax {
+    line_comments: &["#"],
+    block_comments: &[],
+    nested: false,
+    nested_comments: &[],
+    quotes: &[
+        ("\"\"\"", "\"\"\""),
+        ("'''", "'''"),
+        ("\"", "\""),
+        ("'", "'"),
+    ],
+    verbatim_quotes: &[],
+    char_literals: false,
+    docs: DocSyntax {
+        line_comments: &[],
+        block_comments: &[],
+        docstrings: &[("\"\"\"", "\"\"\""), ("'''", "'''")],
+        attributes: &[],
+    },
+};
+
+const ELIXIR: LangSyntax = LangSyntax {
+    line_comments: &["#"],
+    block_comments: &[],
+    nested: false,
+    nested_comments: &[],
+    quotes: &[
+        ("\"\"\"", "\"\"\""),
+        ("'''", "'''"),
+        ("\"", "\""),
+        ("'", "'"),
+    ],
+    verbatim_quotes: &[],
+    char_literals: false,
+    docs: DocSyntax {
+        line_comments: &[],
+        block_comments: &[],
+        docstrings: &[],
+        attributes: &["@doc", "@moduledoc", "@typedoc"],
+    },
+};
+
+const SHELL: LangSyntax = LangSyntax {
+    line_comments: &["#"],
+    block_comments: &[],
+    nested: false,
+    nested_comments: &[],
+    quotes: &[("\"", "\"")],
+    verbatim_quotes: &[("'", "'")],
+    char_literals: false,
+    docs: NO_DOCS,
+};
+
+const RUBY: LangSyntax = LangSyntax {
+    line_comments: &["#"],
+    block_comments: &[],
+    nested: false,
+    nested_comments: &[],
+    quotes: &[("\"", "\""), ("'", "'")],
+    verbatim_quotes: &[],
+    char_literals: false,
+    docs: NO_DOCS,
+};
+
+const HASH_AND_C_STYLE: LangSyntax = LangSyntax {
+    line_comments: &["#", "//"],
+    block_comments: &[("/*", "*/")],
+    nested: false,
+    nested_comments: &[],
+    quotes: &[("\"", "\""), ("'", "'")],
+    verbatim_quotes: &[],
+    char_literals: false,
+    docs: JAVADOC,
+};
+
+const DOUBLE_DASH: LangSyntax = LangSyntax {
+    line_comments: &["--"],
+    block_comments: &[],
+    nested: false,
+    nested_comments: &[],
+    quotes: &[],
+    verbatim_quotes: &[],
+    char_literals: false,
+    docs: NO_DOCS,
+};
+
+const SQL: LangSyntax = LangSyntax {
+    line_comments: &["--"],
+    block_comments: &[("/*", "*/")],
+    nested: false,
+    nested_comments: &[],
+    quotes: &[],
+    verbatim_quotes: &[("'", "'")],
+    char_literals: false,
+    docs: NO_DOCS,
+};
+
+const HASKELL: LangSyntax = LangSyntax {
+    line_comments: &["--"],
+    block_comments: &[("{-", "-}")],
+    nested: true,
+    nested_comments: &[],
+    quotes: &[],
+    verbatim_quotes: &[],
+    char_literals: false,
+    docs: DocSyntax {
+        line_comments: &["-- |", "-- ^"],
+        block_comments: &[("{-|", "-}")],
+        docstrings: &[],
+        attributes: &[],
+    },
+};
+
+const ML: LangSyntax = LangSyntax {
+    line_comments: &[],
+    block_comments: &[("(*", "*)")],
+    nested: true,
+    nested_comments: &[],
+    quotes: &[("\"", "\"")],
+    verbatim_quotes: &[],
+    char_literals: false,
+    docs: DocSyntax {
+        line_comments: &[],
+        block_comments: &[("(**", "*)")],
+        docstrings: &[],
+        attributes: &[],
+    },
+};
+
+const SEMICOLON: LangSyntax = LangSyntax {
+    line_comments: &[";"],
+    block_comments: &[],
+    nested: false,
+    nested_comments: &[],
+    quotes: &[("\"", "\"")],
+    verbatim_quotes: &[],
+    char_literals: false,
+    docs: NO_DOCS,
+};
+
+const LISP: LangSyntax = LangSyntax {
+    line_comments: &[";"],
+    block_comments: &[("#|", "|#")],
+    nested: true,
+    nested_comments: &[],
+    quotes: &[("\"", "\"")],
+    verbatim_quotes: &[],
+    char_literals: false,
+    docs: NO_DOCS,
+};
+
+const PERCENT: LangSyntax = LangSyntax {
+    line_comments: &["%"],
+    block_comments: &[],
+    nested: false,
+    nested_comments: &[],
+    quotes: &[],
+    verbatim_quotes: &[],
+    char_literals: false,
+    docs: NO_DOCS,
+};
+
+const MARKUP: LangSyntax = LangSyntax {
+    line_comments: &[],
+    block_comments: &[("<!--", "-->")],
+    nested: false,
+    nested_comments: &[],
+    quotes: &[],
+    verbatim_quotes: &[],
+    char_literals: false,
+    docs: NO_DOCS,
+};
+
+/// Comment syntax keyed by the language names used in `LANGS_MAP`.
+/// Languages missing from this map have all their non-blank lines counted as code.
+pub static SYNTAX_MAP: SyntaxMap = phf_map! {
+        "ActionScript" => C_STYLE,
+        "Ada" => DOUBLE_DASH,
+        "Agda" => HASKELL,
+        "AMPL" => HASH,
+        "AppleScript" => LangSyntax {
+            line_comments: &["--", "#"],
+            block_comments: &[("(*", "*)")],
+            nested: true,
+            nested_comments: &[],
+            quotes: &[],
+            verbatim_quotes: &[],
+            char_literals: false,
+            docs: NO_DOCS,
+        },
+        "Arduino" => C_STYLE,
+        "AspectJ" => C_STYLE,
+        "Assembly" => SEMICOLON,
+        "AutoHotkey" => SEMICOLON,
+        "Awk" => HASH,
+        "Batchfile" => LangSyntax {
+            line_comments: &["::", "REM", "rem", "@REM", "@rem"],
+            block_comments: &[],
+            nested: false,
+            nested_comments: &[],
+            quotes: &[],
+            verbatim_quotes: &[],
+            char_literals: false,
+            docs: NO_DOCS,
+        },
+        "Bison" => C_STYLE,
+        "C" => C_STYLE,
+        "C#" => CSHARP,
+        "C++" => C_STYLE,
+        "CMake" => HASH,
+        "CSS" => C_BLOCK_ONLY,
+        "Capn Proto" => HASH,
+        "Ceylon" => C_STYLE,
+        "Chapel" => C_STYLE,
+        "Clojure" => SEMICOLON,
+        "CoffeeScript" => LangSyntax {
+            line_comments: &["#"],
+            block_comments: &[("###", "###")],
+            nested: false,
+            nested_comments: &[],
+            quotes: &[],
+            verbatim_quotes: &[],
+            char_literals: false,
+            docs: NO_DOCS,
+        },
+        "Common Lisp" => LISP,
+        "Component Pascal" => ML,
+        "Coq" => ML,
+        "Crystal" => RUBY,
+        "Cuda" => C_STYLE,
+        "Cython" => PYTHON,
+        "D" => LangSyntax {
+            line_comments: &["//"],
+            block_comments: &[("/*", "*/")],
+            nested: false,
+            nested_comments: &[("/+", "+/")],
+            quotes: &[("\"", "\""), ("'", "'")],
+            verbatim_quotes: &[("`", "`"), ("r\"", "\"")],
+            char_literals: false,
+            docs: DocSyntax {
+                line_comments: &["///"],
+                block_comments: &[("/**", "*/"), ("/++", "+/")],
+                docstrings: &[],
+                attributes: &[],
+            },
+        },
+        "DTrace" => C_STYLE,
+        "Dart" => C_STYLE_NESTED,
+        "Dockerfile" => HASH,
+        "Eiffel" => DOUBLE_DASH,
+        "Elixir" => ELIXIR,
+        "Elm" => HASKELL,
+        "Emacs Lisp" => SEMICOLON,
+        "Erlang" => PERCENT,
+        "F#" => LangSyntax {
+            line_comments: &["//"],
+            block_comments: &[("(*", "*)")],
+            nested: true,
+            nested_comments: &[],
+            quotes: &[],
+            verbatim_quotes: &[],
+            char_literals: false,
+            docs: DocSyntax {
+                line_comments: &["///"],
+                block_comments: &[("(**", "*)")],
+                docstrings: &[],
+                attributes: &[],
+            },
+        },
+        "FORTRAN" => LangSyntax {
+            line_comments: &["!"],
+            block_comments: &[],
+            nested: false,
+            nested_comments: &[],
+            quotes: &[],
+            verbatim_quotes: &[],
+            char_literals: false,
+            docs: NO_DOCS,
+        },
+        "GDScript" => PYTHON,
+        "GLSL" => C_STYLE,
+        "Gentoo Ebuild" => SHELL,
+        "Gentoo Eclass" => SHELL,
+        "Gnuplot" => HASH,
+        "Go" => GO,
+        "Go Module" => GO,
+        "Gradle" => C_STYLE,
+        "GraphQL" => HASH,
+        "Groovy" => C_STYLE,
+        "HCL" => HASH_AND_C_STYLE,
+        "HLSL" => C_STYLE,
+        "HTML" => MARKUP,
+        "Haskell" => HASKELL,
+        "Haxe" => C_STYLE,
+        "Idris" => HASKELL,
+        "INI" => LangSyntax {
+            line_comments: &[";", "#"],
+            block_comments: &[],
+            nested: false,
+            nested_comments: &[],
+            quotes: &[],
+            verbatim_quotes: &[],
+            char_literals: false,
+            docs: NO_DOCS,
+        },
+        "Java" => C_STYLE,
+        "JavaScript" => JAVASCRIPT,
+        "JSON5" => C_STYLE,
+        "Just" => HASH,
+        "Julia" => LangSyntax {
+            line_comments: &["#"],
+            block_comments: &[("#=", "=#")],
+            nested: true,
+            nested_comments: &[],
+            quotes: &[],
+            verbatim_quotes: &[],
+            char_literals: false,
+            docs: NO_DOCS,
+        },
+        "Kotlin" => C_STYLE_NESTED,
+        "LFE" => SEMICOLON,
+        "Less" => C_STYLE,
+        "LilyPond" => LangSyntax {
+            line_comments: &["%"],
+            block_comments: &[("%{", "%}")],
+            nested: false,
+            nested_comments: &[],
+            quotes: &[],
+            verbatim_quotes: &[],
+            char_literals: false,
+            docs: NO_DOCS,
+        },
+        "Logtalk" => PERCENT,
+        "Lua" => LangSyntax {
+            line_comments: &["--"],
+            block_comments: &[("--[[", "]]")],
+            nested: false,
+            nested_comments: &[],
+            quotes: &[("\"", "\""), ("'", "'")],
+            verbatim_quotes: &[("[[", "]]")],
+            char_literals: false,
+            docs: NO_DOCS,
+        },
+        "Makefile" => HASH,
+        "Mathematica" => ML,
+        "Meson" => HASH,
+        "Matlab" => LangSyntax {
+            line_comments: &["%"],
+            block_comments: &[("%{", "%}")],
+            nested: false,
+            nested_comments: &[],
+            quotes: &[],
+            verbatim_quotes: &[],
+            char_literals: false,
+            docs: NO_DOCS,
+        },
+        "Modula-2" => ML,
+        "NewLisp" => SEMICOLON,
+        "Nginx" => HASH,
+        "Nimrod" => HASH,
+        "Nix" => LangSyntax {
+            line_comments: &["#"],
+            block_comments: &[("/*", "*/")],
+            nested: false,
+            nested_comments: &[],
+            quotes: &[],
+            verbatim_quotes: &[],
+            char_literals: false,
+            docs: NO_DOCS,
+        },
+        "NumPy" => PYTHON,
+        "OCaml" => ML,
+        "Objective-C" => C_STYLE,
+        "Objective-J" => C_STYLE,
+        "OpenCL" => C_STYLE,
+        "Oz" => PERCENT,
+        "PHP" => HASH_AND_C_STYLE,
+        "PLSQL" => SQL,
+        "Pascal" => LangSyntax {
+            line_comments: &["//"],
+            block_comments: &[("{", "}"), ("(*", "*)")],
+            nested: false,
+            nested_comments: &[],
+            quotes: &[],
+            verbatim_quotes: &[],
+            char_literals: false,
+            docs: NO_DOCS,
+        },
+        "Perl" => RUBY,
+        "Perl6" => RUBY,
+        "Pike" => C_STYLE,
+        "PostScript" => PERCENT,
+        "PowerShell" => LangSyntax {
+            line_comments: &["#"],
+            block_comments: &[("<#", "#>")],
+            nested: false,
+            nested_comments: &[],
+            quotes: &[],
+            verbatim_quotes: &[],
+            char_literals: false,
+            docs: NO_DOCS,
+        },
+        "Processing" => C_STYLE,
+        "Prolog" => LangSyntax {
+            line_comments: &["%"],
+            block_comments: &[("/*", "*/")],
+            nested: false,
+            nested_comments: &[],
+            quotes: &[],
+            verbatim_quotes: &[],
+            char_literals: false,
+            docs: NO_DOCS,
+        },
+        "Protocol Buffer" => C_STYLE,
+        "Puppet" => HASH,
+        "PureScript" => HASKELL,
+        "Python" => PYTHON,
+        "QMake" => HASH,
+        "QML" => JAVASCRIPT,
+        "R" => HASH,
+        "Racket" => LISP,
+        "Rebol" => SEMICOLON,
+        "Red" => SEMICOLON,
+        "RenderScript" => C_STYLE,
+        "Ruby" => RUBY,
+        "Rust" => RUST,
+        "SCSS" => C_STYLE,
+        "SQL" => SQL,
+        "SQLPL" => SQL,
+        "Sage" => PYTHON,
+        "Sass" => C_STYLE,
+        "Scala" => C_STYLE_NESTED,
+        "Scheme" => LISP,
+        "Scilab" => LangSyntax {
+            line_comments: &["//"],
+            block_comments: &[],
+            nested: false,
+            nested_comments: &[],
+            quotes: &[],
+            verbatim_quotes: &[],
+            char_literals: false,
+            docs: NO_DOCS,
+        },
+        "Shell" => SHELL,
+        "SourcePawn" => C_STYLE,
+        "Squirrel" => C_STYLE,
+        "Standard ML" => ML,
+        "Stylus" => C_STYLE,
+        "Swift" => C_STYLE_NESTED,
+        "SystemVerilog" => VERILOG,
+        "TOML" => HASH,
+        "Tcl" => HASH,
+        "Tcsh" => SHELL,
+        "TeX" => PERCENT,
+        "Thrift" => C_STYLE,
+        "TypeScript" => JAVASCRIPT,
+        "UnrealScript" => C_STYLE,
+        "VHDL" => DOUBLE_DASH,
+        "Vala" => C_STYLE,
+        "Verilog" => VERILOG,
+        "VimL" => LangSyntax {
+            line_comments: &["\""],
+            block_comments: &[],
+            nested: false,
+            nested_comments: &[],
+            quotes: &[],
+            verbatim_quotes: &[],
+            char_literals: false,
+            docs: NO_DOCS,
+        },
+        "Visual Basic" => LangSyntax {
+            line_comments: &["'"],
+            block_comments: &[],
+            nested: false,
+            nested_comments: &[],
+            quotes: &[],
+            verbatim_quotes: &[],
+            char_literals: false,
+            docs: NO_DOCS,
+        },
+        "Vue" => MARKUP,
+        "WebIDL" => C_STYLE,
+        "XML" => MARKUP,
+        "XSLT" => MARKUP,
+        "SVG" => MARKUP,
+        "Xtend" => C_STYLE,
+        "YAML" => HASH,
+        "Zephir" => C_STYLE,
+        "fish" => SHELL,
+};
+
+/// Resolves a language name as written in a modeline, `linguist-language` or
+/// a config file, e.g. `python`, `cpp` or `Objective-C`, to one of the names
+/// used in `LANGS_MAP`.
+pub fn lang_from_name(name: &str) -> Option<&'static str> {
+    let name = name.to_lowercase();
+    if let Some(lang) = LANG_ALIASES.get(&name) {
+        return Some(lang);
+    }
+
+    LANGS_MAP
+        .values()
+        .chain(FILENAMES_MAP.values())
+        .chain(SYNTAX_MAP.keys())
+        .copied()
+        .find(|lang| {
+            let lang = lang.to_lowercase();
+            lang == name || lang == name.replace('-', " ")
+        })
+}
+
+/// Languages known at runtime: the built-in maps, plus languages and extension
+/// or filename mappings registered on top of them, which take precedence.
+#[derive(Default)]
+pub struct LangRegistry {
+    syntaxes: HashMap<&'static str, LangSyntax>,
+    extensions: HashMap<String, &'static str>,
+    filenames: HashMap<String, &'static str>,
+    heuristics: HashMap<String, Vec<(&'static str, Regex)>>,
+}
+
+impl LangRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a new language, or replaces the syntax of a known one, and
+    /// returns its name. Names of new languages live for the rest of the program.
+    pub fn add_language(&mut self, name: &str, syntax: LangSyntax) -> &'static str {
+        let name = self
+            .syntaxes
+            .keys()
+            .chain(LANGS_MAP.values())
+            .chain(FILENAMES_MAP.values())
+            .chain(SYNTAX_MAP.keys())
+            .find(|lang| **lang == name)
+            .copied()
+            .unwrap_or_else(|| Box::leak(name.to_string().into_boxed_str()));
+        self.syntaxes.insert(name, syntax);
+        name
+    }
+
+    /// Maps files with the extension (without the dot) to the language.
+    pub fn add_extension(&mut self, ext: &str, lang: &str) -> Result<()> {
+        let lang = self.resolve_or_err(lang)?;
+        self.extensions.insert(ext.to_string(), lang);
+        Ok(())
+    }
+
+    /// Maps files with exactly this name to the language.
+    pub fn add_filename(&mut self, file_name: &str, lang: &str) -> Result<()> {
+        let lang = self.resolve_or_err(lang)?;
+        self.filenames.insert(file_name.to_string(), lang);
+        Ok(())
+    }
+
+    /// Picks the language for files with the extension (without the dot) whose
+    /// contents match the regex `pattern`. These heuristics are tried in the
+    /// order they were added, before the ones of `HEURISTICS_MAP`.
+    pub fn add_heuristic(&mut self, ext: &str, lang: &str, pattern: &str) -> Result<()> {
+        let lang = self.resolve_or_err(lang)?;
+        let regex = Regex::new(pattern)
+            .with_context(|| format!("Invalid heuristic pattern: {}", pattern))?;
+        self.heuristics
+            .entry(ext.to_string())
+            .or_default()
+            .push((lang, regex));
+        Ok(())
+    }
+
+    /// Like `lang_from_name`, but also knows the registered languages.
+    pub fn resolve(&self, name: &str) -> Option<&'static str> {
+        self.syntaxes
+            .keys()
+            .find(|lang| lang.eq_ignore_ascii_case(name))
+            .copied()
+            .or_else(|| lang_from_name(name))
+    }
+
+    pub fn extension_lang(&self, ext: &str) -> Option<&'static str> {
+        self.extensions.get(ext).copied()
+    }
+
+    pub fn filename_lang(&self, file_name: &str) -> Option<&'static str> {
+        self.filenames.get(file_name).copied()
+    }
+
+    pub fn heuristics(&self, ext: &str) -> Option<&[(&'static str, Regex)]> {
+        self.heuristics.get(ext).map(Vec::as_slice)
+    }
+
+    pub fn syntax(&self, lang: &str) -> Option<&LangSyntax> {
+        self.syntaxes.get(lang).or_else(|| SYNTAX_MAP.get(lang))
+    }
+
+    fn resolve_or_err(&self, name: &str) -> Result<&'static str> {
+        self.resolve(name)
+            .ok_or_else(|| anyhow!("Unknown language: {}", name))
+    }
+}
@@ -1,3 +1,9 @@
+pub mod badge;
+pub mod baseline;
+pub mod config;
 pub mod get_stats;
+pub mod git;
+pub mod gitattributes;
+pub mod html;
 pub mod langs;
 pub mod output;
@@ -1,36 +1,405 @@
-use anyhow::Result;
-use clap::Parser;
-use loc_stats::get_stats::{get_stats_parallel, GetStatsOptions};
-use std::path::PathBuf;
+use anyhow::{bail, Context, Result};
+use clap::{Parser, Subcommand};
+use loc_stats::baseline::Baseline;
+use loc_stats::config::{Config, Thresholds};
+use loc_stats::get_stats::{
+    get_stats_roots, DirStat, GetStatsOptions, RootStats, SortOrder, Stats,
+};
+use loc_stats::git::{get_stats_rev, CommitStats, Sampling, TreeCounter};
+use loc_stats::output::{history_to_delimited, pretty_subtotals, JsonReport, OutputFormat};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Exit status when a threshold is exceeded, apart from errors (1) and usage errors (2).
+const THRESHOLD_EXCEEDED: i32 = 3;
 
 #[derive(Parser, Debug)]
-#[clap(author, version, about, long_about = None)]
+#[clap(
+    author,
+    version,
+    about,
+    long_about = None,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 struct Args {
-    path: String,
+    #[clap(subcommand)]
+    command: Option<Command>,
+
+    /// Also shows the lines and files of each path
+    #[clap(long, action)]
+    subtotals: bool,
+
+    /// Lists every counted file with its language, lines and size
+    #[clap(long, action)]
+    files: bool,
 
-    /// Gives the output in JSON format
+    /// Shows a tree of directories with their lines, files and main language
+    #[clap(long, action)]
+    by_dir: bool,
+
+    /// Rolls up directories deeper than this into their parent in --by-dir
+    #[clap(long, value_name = "N", requires = "by-dir")]
+    depth: Option<usize>,
+
+    /// Gives the output in JSON format. Same as --format json
     #[clap(short, long, action)]
     json: bool,
 
+    /// Output format. Defaults to pretty, unless set in the config file
+    #[clap(short, long, value_enum)]
+    format: Option<OutputFormat>,
+
+    /// Order of languages, files and directories. Defaults to lines, unless set
+    /// in the config file
+    #[clap(short, long, value_enum)]
+    sort: Option<SortOrder>,
+
+    /// Saves the lines per language and per file, to be compared against later
+    #[clap(long, value_name = "FILE")]
+    save_baseline: Option<PathBuf>,
+
+    /// Shows how the lines changed since the baseline saved in the file
+    #[clap(long, value_name = "FILE")]
+    compare: Option<PathBuf>,
+
+    /// Writes the output to the given file instead of the standard output
+    #[clap(short, long, value_name = "FILE")]
+    output: Option<PathBuf>,
+
+    /// Counts the files of a branch, tag or commit, read from git instead of the
+    /// working tree
+    #[clap(long, value_name = "COMMIT-ISH")]
+    rev: Option<String>,
+
+    #[clap(flatten)]
+    walk: WalkArgs,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Checks the lines against thresholds given on the command line or in the
+    /// config file, printing every one exceeded. Exits with status 3 if any is
+    Check(CheckArgs),
+    /// Counts the lines of the commits of a branch, reading them from git
+    /// without checking them out, as CSV, TSV or JSON to plot over time
+    History(HistoryArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct CheckArgs {
+    /// Upper limit of the total lines
+    #[clap(long, value_name = "N")]
+    max_total_loc: Option<usize>,
+
+    /// Upper limit of a language's share of lines, e.g. JavaScript=5. Can be
+    /// given multiple times
+    #[clap(long, value_name = "LANG=PERCENT", value_parser = parse_lang_percent)]
+    max_percent: Vec<(String, f32)>,
+
+    /// Upper limit of the lines of a single file
+    #[clap(long, value_name = "N")]
+    max_file_loc: Option<usize>,
+
+    /// Lower limit of the share of comment and doc lines among non-blank lines
+    #[clap(long, value_name = "RATIO")]
+    min_comment_ratio: Option<f32>,
+
+    /// Counts the files of a branch, tag or commit, read from git instead of the
+    /// working tree
+    #[clap(long, value_name = "COMMIT-ISH")]
+    rev: Option<String>,
+
+    #[clap(flatten)]
+    walk: WalkArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct HistoryArgs {
+    /// Branch, or any other commit-ish, whose commits are counted
+    #[clap(short, long, default_value = "HEAD")]
+    branch: String,
+
+    /// Which commits are counted
+    #[clap(long, value_enum, default_value_t)]
+    every: Sampling,
+
+    /// Only counts commits more recent than the date, e.g. 2024-01-31 or "2 years ago"
+    #[clap(long, value_name = "DATE")]
+    since: Option<String>,
+
+    /// Output format, one of csv, tsv or json. Defaults to csv
+    #[clap(short, long, value_enum)]
+    format: Option<OutputFormat>,
+
+    /// Writes the output to the given file instead of the standard output
+    #[clap(short, long, value_name = "FILE")]
+    output: Option<PathBuf>,
+
+    #[clap(flatten)]
+    walk: WalkArgs,
+}
+
+// which files are counted and how, shared by every command
+#[derive(clap::Args, Debug)]
+struct WalkArgs {
+    /// Paths to count. Paths inside another one are only counted once
+    #[clap(required = true)]
+    paths: Vec<PathBuf>,
+
     /// Ignores files listed in .gitignore. Defaults to true
     #[clap(long, action)]
     gitignore: Option<bool>,
+
+    /// Honors linguist-* attributes from .gitattributes, leaving out vendored,
+    /// generated and documentation files. Defaults to true
+    #[clap(long, action)]
+    linguist: Option<bool>,
+
+    /// Only counts files matching the glob. Can be given multiple times
+    #[clap(short, long, value_name = "GLOB")]
+    include: Vec<String>,
+
+    /// Skips files and directories matching the glob. Can be given multiple times
+    #[clap(short, long, value_name = "GLOB")]
+    exclude: Vec<String>,
+
+    /// Reads settings from the given file instead of the .loc-stats.toml
+    /// found in the first path or its ancestors
+    #[clap(short, long, value_name = "FILE")]
+    config: Option<PathBuf>,
+}
+
+impl WalkArgs {
+    fn load(&self) -> Result<(Config, GetStatsOptions)> {
+        // the config is looked up from the first path
+        let config = match &self.config {
+            Some(file) => Config::load(file)?,
+            None => Config::discover(&self.paths[0])?.unwrap_or_default(),
+        };
+
+        // command line arguments take precedence over the config file
+        let mut options = config.stats_options()?;
+        if let Some(gitignore) = self.gitignore {
+            options.gitignore = gitignore;
+        }
+        if let Some(linguist) = self.linguist {
+            options.linguist = linguist;
+        }
+        options.include.extend(self.include.iter().cloned());
+        options.exclude.extend(self.exclude.iter().cloned());
+        Ok((config, options))
+    }
+}
+
+fn parse_lang_percent(arg: &str) -> Result<(String, f32), String> {
+    let (lang, percent) = arg
+        .split_once('=')
+        .ok_or_else(|| "expected LANG=PERCENT".to_string())?;
+    let percent = percent
+        .trim_end_matches('%')
+        .parse()
+        .map_err(|_| format!("invalid percent: {}", percent))?;
+    Ok((lang.to_string(), percent))
 }
 
 fn main() -> Result<()> {
-    let args = Args::parse();
-    let options = GetStatsOptions {
-        gitignore: args.gitignore.unwrap_or(true),
+    let mut args = Args::parse();
+    match args.command.take() {
+        Some(Command::Check(check_args)) => {
+            if !check(check_args)? {
+                std::process::exit(THRESHOLD_EXCEEDED);
+            }
+        }
+        Some(Command::History(history_args)) => history(history_args)?,
+        None => report(args)?,
+    }
+    Ok(())
+}
+
+/// Prints every threshold exceeded, returning whether all of them passed.
+fn check(args: CheckArgs) -> Result<bool> {
+    let (mut config, mut options) = args.walk.load()?;
+    config.thresholds.merge(Thresholds {
+        max_total_loc: args.max_total_loc,
+        max_percent: args.max_percent.into_iter().collect(),
+        max_file_loc: args.max_file_loc,
+        min_comment_ratio: args.min_comment_ratio,
+    });
+    if config.thresholds.is_empty() {
+        bail!("No thresholds to check. Give them as options or in the [thresholds] table of the config file");
+    }
+    options.files = config.thresholds.max_file_loc.is_some();
+
+    let roots = count_roots(&args.walk.paths, args.rev.as_deref(), &options)?;
+    let stats = Stats::combine(roots.iter().map(|root| &root.stats));
+    let violations = config.thresholds.violations(&stats, &options.langs);
+    if violations.is_empty() {
+        println!(
+            "All checks passed: {} lines of code in {} files",
+            stats.total_loc, stats.number_of_files
+        );
+    }
+    for violation in &violations {
+        println!("{}", violation);
+    }
+    Ok(violations.is_empty())
+}
+
+/// Prints the stats of each commit.
+fn history(args: HistoryArgs) -> Result<()> {
+    let (config, options) = args.walk.load()?;
+    let format = args.format.unwrap_or(OutputFormat::Csv);
+    if !matches!(
+        format,
+        OutputFormat::Csv | OutputFormat::Tsv | OutputFormat::Json
+    ) {
+        bail!("history can only be output as csv, tsv or json");
+    }
+
+    let mut counter = TreeCounter::new(&args.walk.paths, &options)?;
+    let commits = counter
+        .repo()
+        .commits(&args.branch, args.every, args.since.as_deref())?;
+    let mut history = Vec::new();
+    for commit in commits {
+        let roots = counter.count(&commit.id)?;
+        let mut stats = Stats::combine(roots.iter().map(|root| &root.stats));
+        stats.sort(config.sort.unwrap_or_default());
+        history.push(CommitStats { commit, stats });
+    }
+
+    let output = match format {
+        OutputFormat::Json => serde_json::to_string_pretty(&history)? + "\n",
+        OutputFormat::Tsv => history_to_delimited(&history, '\t'),
+        _ => history_to_delimited(&history, ','),
     };
+    write_output(&output, args.output.as_deref())
+}
 
-    let stats = get_stats_parallel(PathBuf::from(&args.path).as_path(), &options)?;
+/// Prints the stats.
+fn report(args: Args) -> Result<()> {
+    let (config, mut options) = args.walk.load()?;
 
-    if args.json {
-        let json = serde_json::to_string_pretty(&stats)?;
-        println!("{}", json);
+    let format = match args.format {
+        Some(format) => format,
+        None if args.json => OutputFormat::Json,
+        None => config.format.unwrap_or(OutputFormat::Pretty),
+    };
+    if format == OutputFormat::Pretty && args.output.is_some() {
+        bail!("--output can't be used with the pretty format");
+    }
+    if args.compare.is_some() && !matches!(format, OutputFormat::Pretty | OutputFormat::Json) {
+        bail!("--compare can only be used with the pretty and json formats");
+    }
+    let baseline = args.compare.as_deref().map(Baseline::load).transpose()?;
+    // the HTML report has a treemap of the directories and a table of the files
+    let by_dir = args.by_dir || format == OutputFormat::Html;
+    let list_files = args.files || format == OutputFormat::Html;
+
+    // the directory tree and the baselines are built from the files
+    options.files = list_files || by_dir || baseline.is_some() || args.save_baseline.is_some();
+
+    let mut roots = count_roots(&args.walk.paths, args.rev.as_deref(), &options)?;
+    let mut dirs: BTreeMap<String, DirStat> = if by_dir {
+        roots
+            .iter()
+            .map(|root| {
+                let name = root.path.display().to_string();
+                (
+                    name,
+                    DirStat::from_files(&root.path, &root.stats.files, args.depth),
+                )
+            })
+            .collect()
     } else {
-        stats.pretty_output();
+        BTreeMap::new()
+    };
+    let mut stats = Stats::combine(roots.iter().map(|root| &root.stats));
+
+    let snapshot = Baseline::new(&roots);
+    if let Some(file) = &args.save_baseline {
+        snapshot.save(file)?;
+    }
+    let comparison = baseline.map(|baseline| baseline.compare(&snapshot));
+
+    if !list_files {
+        stats.files.clear();
+        for root in &mut roots {
+            root.stats.files.clear();
+        }
     }
 
+    let sort = args.sort.or(config.sort).unwrap_or_default();
+    stats.sort(sort);
+    for root in &mut roots {
+        root.stats.sort(sort);
+    }
+    for dir in dirs.values_mut() {
+        dir.sort(sort);
+    }
+
+    let output = match format {
+        OutputFormat::Pretty => {
+            stats.pretty_output();
+            if args.subtotals {
+                pretty_subtotals(&roots);
+            }
+            for (name, dir) in &dirs {
+                dir.pretty_output(name);
+            }
+            if let Some(comparison) = &comparison {
+                comparison.pretty_output();
+            }
+            None
+        }
+        OutputFormat::Json => {
+            let report = JsonReport {
+                stats: &stats,
+                roots: args.subtotals.then_some(roots.as_slice()),
+                dirs: args.by_dir.then_some(&dirs),
+                comparison: comparison.as_ref(),
+            };
+            let json = serde_json::to_string_pretty(&report)?;
+            Some(json + "\n")
+        }
+        OutputFormat::Csv => Some(stats.to_delimited(',', args.files)),
+        OutputFormat::Tsv => Some(stats.to_delimited('\t', args.files)),
+        OutputFormat::Markdown => Some(stats.to_markdown()),
+        OutputFormat::Html => Some(stats.to_html(&dirs)),
+        OutputFormat::SvgBadge => Some(stats.to_svg_badge()),
+        OutputFormat::SvgLanguages => Some(stats.to_svg_languages_badge()),
+    };
+    if let Some(output) = output {
+        write_output(&output, args.output.as_deref())?;
+    }
     Ok(())
 }
+
+/// Counts the paths in the working tree, or as of `rev` if given.
+fn count_roots(
+    paths: &[PathBuf],
+    rev: Option<&str>,
+    options: &GetStatsOptions,
+) -> Result<Vec<RootStats>> {
+    match rev {
+        Some(rev) => get_stats_rev(paths, rev, options),
+        None => get_stats_roots(paths, options),
+    }
+}
+
+/// Writes to `file`, or to the standard output if there is none.
+fn write_output(output: &str, file: Option<&Path>) -> Result<()> {
+    match file {
+        Some(file) => {
+            fs::write(file, output).with_context(|| format!("Could not write {}", file.display()))
+        }
+        None => {
+            print!("{}", output);
+            Ok(())
+        }
+    }
+}
@@ -1,8 +1,40 @@
-use std::iter::FromIterator;
+use std::{borrow::Cow, collections::BTreeMap};
 
 use colored::Colorize;
+use serde::{Deserialize, Serialize};
 
-use crate::get_stats::Stats;
+use crate::baseline::{Comparison, FileChange};
+use crate::get_stats::{DirStat, RootStats, Stats};
+use crate::git::CommitStats;
+
+/// The JSON output: the combined stats, followed by the optional sections.
+/// Serialized directly rather than through `serde_json::Value`, which would
+/// sort the languages by name.
+#[derive(Serialize)]
+pub struct JsonReport<'a> {
+    #[serde(flatten)]
+    pub stats: &'a Stats,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub roots: Option<&'a [RootStats]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dirs: Option<&'a BTreeMap<String, DirStat>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comparison: Option<&'a Comparison>,
+}
+
+/// How the stats are printed.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutputFormat {
+    Pretty,
+    Json,
+    Csv,
+    Tsv,
+    Markdown,
+    Html,
+    SvgBadge,
+    SvgLanguages,
+}
 
 fn print_banner() {
     println!(
@@ -29,37 +61,541 @@ impl Stats {
             "Number of files:".bold().bright_white(),
             number_of_files
         );
+
+        for (kind, stat) in &self.excluded {
+            println!(
+                "{} {} files, {} lines",
+                format!("Excluded {}:", kind).bright_black(),
+                stat.files,
+                stat.loc
+            );
+        }
+        if self.binaries.files > 0 {
+            println!(
+                "{} {} files, {} bytes",
+                "Skipped binary:".bright_black(),
+                self.binaries.files,
+                self.binaries.bytes
+            );
+        }
         println!();
 
-        let longest_name_len = &self
+        let longest_name_len = self
             .by_lang
             .keys()
             .map(|name| name.len())
             .max()
-            .unwrap_or_default();
+            .unwrap_or_default()
+            .max("Language".len());
 
-        let longest_loc_len = &&self
+        // every numeric column is as wide as the widest number in the table
+        let number_width = self
             .by_lang
             .values()
-            .map(|stats| format!("{}", stats.loc).len())
+            .map(|stats| format!("{}", stats.loc.max(stats.bytes as usize)).len())
             .max()
-            .unwrap_or_default();
-
-        let mut langs_vec = Vec::from_iter(&self.by_lang);
-        langs_vec.sort_by(|a, b| b.1.loc.cmp(&a.1.loc));
+            .unwrap_or_default()
+            .max("Comments".len());
 
-        for entry in langs_vec {
-            let lang_name = entry.0;
-            let stat = entry.1;
+        println!(
+            "{}",
+            format!(
+                "{:width$}\t{:>n$}\t{:>n$}\t{:>n$}\t{:>n$}\t{:>n$}\t{:>n$}\t{:>n$}\t{:>n$}\t{:>n$}\t{:>6}",
+                "Language",
+                "Files",
+                "Lines",
+                "Code",
+                "Comments",
+                "Docs",
+                "Blanks",
+                "Bytes",
+                "Avg",
+                "Max",
+                "%",
+                width = longest_name_len,
+                n = number_width
+            )
+            .bold()
+        );
+        for (lang_name, stat) in &self.by_lang {
             println!(
-                "{:width$}\t{:>loc_width$}\t{:>5}%",
+                "{:width$}\t{:>n$}\t{:>n$}\t{:>n$}\t{:>n$}\t{:>n$}\t{:>n$}\t{:>n$}\t{:>n$}\t{:>n$}\t{:>5}%",
                 lang_name,
+                stat.files,
                 stat.loc,
+                stat.code,
+                stat.comments,
+                stat.docs,
+                stat.blanks,
+                stat.bytes,
+                stat.avg_loc,
+                stat.max_loc,
                 stat.percent,
                 width = longest_name_len,
-                loc_width = longest_loc_len
+                n = number_width
+            );
+        }
+        println!();
+
+        if !self.files.is_empty() {
+            self.pretty_files();
+        }
+    }
+
+    /// Formats the stats of each language, or of each file if `per_file` is set, as
+    /// values separated by `delimiter` (`,` for CSV or `\t` for TSV) with a header row.
+    pub fn to_delimited(&self, delimiter: char, per_file: bool) -> String {
+        let mut output = String::new();
+
+        if per_file {
+            push_record(
+                &mut output,
+                &[
+                    "path", "language", "lines", "code", "comments", "docs", "blanks", "bytes",
+                ],
+                delimiter,
+            );
+            for file in &self.files {
+                push_record(
+                    &mut output,
+                    &[
+                        &file.path.display().to_string(),
+                        file.lang,
+                        &file.loc.to_string(),
+                        &file.code.to_string(),
+                        &file.comments.to_string(),
+                        &file.docs.to_string(),
+                        &file.blanks.to_string(),
+                        &file.bytes.to_string(),
+                    ],
+                    delimiter,
+                );
+            }
+            return output;
+        }
+
+        push_record(
+            &mut output,
+            &[
+                "language",
+                "files",
+                "lines",
+                "code",
+                "comments",
+                "docs",
+                "blanks",
+                "bytes",
+                "avg_lines",
+                "max_lines",
+                "percent",
+            ],
+            delimiter,
+        );
+        for (lang_name, stat) in &self.by_lang {
+            push_record(
+                &mut output,
+                &[
+                    lang_name,
+                    &stat.files.to_string(),
+                    &stat.loc.to_string(),
+                    &stat.code.to_string(),
+                    &stat.comments.to_string(),
+                    &stat.docs.to_string(),
+                    &stat.blanks.to_string(),
+                    &stat.bytes.to_string(),
+                    &stat.avg_loc.to_string(),
+                    &stat.max_loc.to_string(),
+                    &stat.percent.to_string(),
+                ],
+                delimiter,
+            );
+        }
+        output
+    }
+
+    /// Formats the stats of each language as a GitHub-flavored Markdown table,
+    /// followed by a row with the totals.
+    pub fn to_markdown(&self) -> String {
+        let mut rows = vec![[
+            "Language".to_string(),
+            "Files".to_string(),
+            "Lines".to_string(),
+            "%".to_string(),
+        ]];
+        for (lang_name, stat) in &self.by_lang {
+            rows.push([
+                lang_name.replace('|', "\\|"),
+                stat.files.to_string(),
+                stat.loc.to_string(),
+                stat.percent.to_string(),
+            ]);
+        }
+        let total_percent = if self.by_lang.is_empty() { 0 } else { 100 };
+        rows.push([
+            "**Total**".to_string(),
+            format!("**{}**", self.number_of_files),
+            format!("**{}**", self.total_loc),
+            format!("**{}**", total_percent),
+        ]);
+
+        let mut widths = [0; 4];
+        for row in &rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.len());
+            }
+        }
+
+        let mut output = String::new();
+        for (i, row) in rows.iter().enumerate() {
+            let [lang, files, lines, percent] = row;
+            output.push_str(&format!(
+                "| {:w0$} | {:>w1$} | {:>w2$} | {:>w3$} |\n",
+                lang,
+                files,
+                lines,
+                percent,
+                w0 = widths[0],
+                w1 = widths[1],
+                w2 = widths[2],
+                w3 = widths[3]
+            ));
+            // the delimiter row goes below the header, right-aligning the numbers
+            if i == 0 {
+                output.push_str(&format!(
+                    "| {} | {}: | {}: | {}: |\n",
+                    "-".repeat(widths[0]),
+                    "-".repeat(widths[1] - 1),
+                    "-".repeat(widths[2] - 1),
+                    "-".repeat(widths[3] - 1)
+                ));
+            }
+        }
+        output
+    }
+
+    fn pretty_files(&self) {
+        let paths: Vec<String> = self
+            .files
+            .iter()
+            .map(|file| file.path.display().to_string())
+            .collect();
+        let longest_path_len = paths
+            .iter()
+            .map(|path| path.len())
+            .max()
+            .unwrap_or_default()
+            .max("Path".len());
+        let longest_lang_len = self
+            .files
+            .iter()
+            .map(|file| file.lang.len())
+            .max()
+            .unwrap_or_default()
+            .max("Language".len());
+        let number_width = self
+            .files
+            .iter()
+            .map(|file| format!("{}", file.bytes).len())
+            .max()
+            .unwrap_or_default()
+            .max("Lines".len());
+
+        println!(
+            "{}",
+            format!(
+                "{:width$}\t{:lang_width$}\t{:>n$}\t{:>n$}",
+                "Path",
+                "Language",
+                "Lines",
+                "Bytes",
+                width = longest_path_len,
+                lang_width = longest_lang_len,
+                n = number_width
+            )
+            .bold()
+        );
+        for (path, file) in paths.iter().zip(&self.files) {
+            println!(
+                "{:width$}\t{:lang_width$}\t{:>n$}\t{:>n$}",
+                path,
+                file.lang,
+                file.loc,
+                file.bytes,
+                width = longest_path_len,
+                lang_width = longest_lang_len,
+                n = number_width
+            );
+        }
+        println!();
+    }
+}
+
+/// Formats the lines of each language in each commit as CSV or TSV, one record
+/// per commit and language, oldest commit first.
+pub fn history_to_delimited(history: &[CommitStats], delimiter: char) -> String {
+    let mut output = String::new();
+    push_record(
+        &mut output,
+        &[
+            "commit", "date", "tags", "language", "files", "lines", "code", "comments", "docs",
+            "blanks", "bytes",
+        ],
+        delimiter,
+    );
+    for entry in history {
+        let tags = entry.commit.tags.join(" ");
+        for (lang_name, stat) in &entry.stats.by_lang {
+            push_record(
+                &mut output,
+                &[
+                    &entry.commit.id,
+                    &entry.commit.date,
+                    &tags,
+                    lang_name,
+                    &stat.files.to_string(),
+                    &stat.loc.to_string(),
+                    &stat.code.to_string(),
+                    &stat.comments.to_string(),
+                    &stat.docs.to_string(),
+                    &stat.blanks.to_string(),
+                    &stat.bytes.to_string(),
+                ],
+                delimiter,
+            );
+        }
+    }
+    output
+}
+
+fn push_record(output: &mut String, fields: &[&str], delimiter: char) {
+    let fields: Vec<Cow<str>> = fields
+        .iter()
+        .map(|field| escape_field(field, delimiter))
+        .collect();
+    output.push_str(&fields.join(&delimiter.to_string()));
+    output.push('\n');
+}
+
+/// Quotes CSV fields as in RFC 4180. TSV has no quoting, so tabs, line breaks
+/// and backslashes are escaped with a backslash instead.
+fn escape_field(field: &str, delimiter: char) -> Cow<'_, str> {
+    if delimiter == '\t' {
+        if field.contains(['\\', '\t', '\n', '\r']) {
+            return field
+                .replace('\\', "\\\\")
+                .replace('\t', "\\t")
+                .replace('\n', "\\n")
+                .replace('\r', "\\r")
+                .into();
+        }
+    } else if field.contains([delimiter, '"', '\n', '\r']) {
+        return format!("\"{}\"", field.replace('"', "\"\"")).into();
+    }
+    field.into()
+}
+
+/// Prints the lines and files of each path, below the combined table.
+pub fn pretty_subtotals(roots: &[RootStats]) {
+    let paths: Vec<String> = roots
+        .iter()
+        .map(|root| root.path.display().to_string())
+        .collect();
+    let longest_path_len = paths
+        .iter()
+        .map(|path| path.len())
+        .max()
+        .unwrap_or_default()
+        .max("Path".len());
+    let number_width = roots
+        .iter()
+        .map(|root| format!("{}", root.stats.total_loc).len())
+        .max()
+        .unwrap_or_default()
+        .max("Files".len());
+
+    println!(
+        "{}",
+        format!(
+            "{:width$}\t{:>n$}\t{:>n$}",
+            "Path",
+            "Lines",
+            "Files",
+            width = longest_path_len,
+            n = number_width
+        )
+        .bold()
+    );
+    for (path, root) in paths.iter().zip(roots) {
+        println!(
+            "{:width$}\t{:>n$}\t{:>n$}",
+            path,
+            root.stats.total_loc,
+            root.stats.number_of_files,
+            width = longest_path_len,
+            n = number_width
+        );
+    }
+    println!();
+}
+
+impl DirStat {
+    /// Prints the directory tree, indenting subdirectories below their parent.
+    pub fn pretty_output(&self, name: &str) {
+        let mut rows = Vec::new();
+        self.tree_rows(name.to_string(), &mut rows);
+
+        let longest_name_len = rows
+            .iter()
+            .map(|(name, _)| name.len())
+            .max()
+            .unwrap_or_default()
+            .max("Directory".len());
+        let number_width = format!("{}", self.loc).len().max("Lines".len());
+
+        println!(
+            "{}",
+            format!(
+                "{:width$}\t{:>n$}\t{:>n$}\t{}",
+                "Directory",
+                "Lines",
+                "Files",
+                "Language",
+                width = longest_name_len,
+                n = number_width
+            )
+            .bold()
+        );
+        for (name, dir) in rows {
+            println!(
+                "{:width$}\t{:>n$}\t{:>n$}\t{}",
+                name,
+                dir.loc,
+                dir.files,
+                dir.lang.unwrap_or_default(),
+                width = longest_name_len,
+                n = number_width
             );
         }
         println!();
     }
+
+    fn tree_rows<'a>(&'a self, name: String, rows: &mut Vec<(String, &'a DirStat)>) {
+        let indent = " ".repeat(name.len() - name.trim_start().len() + 2);
+        rows.push((name, self));
+
+        for (name, dir) in &self.dirs {
+            dir.tree_rows(format!("{}{}/", indent, name), rows);
+        }
+    }
+}
+
+impl Comparison {
+    /// Prints the change in lines overall, per language and per file, with
+    /// additions in green and removals in red. The change in comment and doc
+    /// lines of each language is shown next to its change in lines.
+    pub fn pretty_output(&self) {
+        println!(
+            "{} {} -> {} ({})",
+            "Lines of code:".bold().bright_white(),
+            self.before,
+            self.after,
+            colored_delta(self.delta)
+        );
+        println!();
+        if self.by_lang.is_empty() && self.files.is_empty() {
+            println!("No changes");
+            println!();
+            return;
+        }
+
+        let longest_name_len = self
+            .by_lang
+            .iter()
+            .map(|lang| lang.lang.len())
+            .max()
+            .unwrap_or_default()
+            .max("Language".len());
+        let number_width = self
+            .by_lang
+            .iter()
+            .map(|lang| format!("{}", lang.before.max(lang.after)).len())
+            .max()
+            .unwrap_or_default()
+            .max("Comments".len());
+
+        println!(
+            "{}",
+            format!(
+                "{:width$}\t{:>n$}\t{:>n$}\t{:>n$}\t{:>n$}\t{:>n$}",
+                "Language",
+                "Before",
+                "After",
+                "Change",
+                "Comments",
+                "Docs",
+                width = longest_name_len,
+                n = number_width
+            )
+            .bold()
+        );
+        for lang in &self.by_lang {
+            // padded before coloring, as the escape codes would count towards the width
+            let padded = |delta| {
+                color_by_sign(
+                    format!("{:>n$}", format_delta(delta), n = number_width),
+                    delta,
+                )
+            };
+            println!(
+                "{:width$}\t{:>n$}\t{:>n$}\t{}\t{}\t{}",
+                lang.lang,
+                lang.before,
+                lang.after,
+                padded(lang.delta),
+                padded(lang.kinds.comments),
+                padded(lang.kinds.docs),
+                width = longest_name_len,
+                n = number_width
+            );
+        }
+        println!();
+
+        for file in &self.files {
+            let marker = match file.change {
+                FileChange::Added => "+".green(),
+                FileChange::Removed => "-".red(),
+                FileChange::Changed => "~".yellow(),
+            };
+            println!(
+                "{} {} ({}, {})",
+                marker,
+                file.path.display(),
+                file.lang,
+                colored_delta(file.delta)
+            );
+        }
+        if !self.files.is_empty() {
+            println!();
+        }
+    }
+}
+
+fn format_delta(delta: i64) -> String {
+    if delta > 0 {
+        format!("+{}", delta)
+    } else {
+        delta.to_string()
+    }
+}
+
+fn colored_delta(delta: i64) -> colored::ColoredString {
+    color_by_sign(format_delta(delta), delta)
+}
+
+fn color_by_sign(text: String, delta: i64) -> colored::ColoredString {
+    match delta.signum() {
+        1 => text.green(),
+        -1 => text.red(),
+        _ => text.normal(),
+    }
 }
@@ -1,9 +1,21 @@
 use anyhow::{Context, Result};
-use loc_stats::get_stats::{get_stats_parallel, GetStatsOptions, LangStat, Stats};
+use indexmap::IndexMap;
+use loc_stats::badge::human_count;
+use loc_stats::baseline::{Baseline, FileChange};
+use loc_stats::config::{Config, Thresholds};
+use loc_stats::get_stats::{
+    dedup_roots, get_stats_parallel, get_stats_roots, BinaryStat, DirStat, ExcludedStat, FileStat,
+    GetStatsOptions, LangStat, SortOrder, Stats,
+};
+use loc_stats::git::{get_stats_rev, Sampling, TreeCounter};
+use loc_stats::langs::{LangRegistry, LangSyntax, NO_DOCS};
+use loc_stats::output::JsonReport;
 use std::{
-    collections::HashMap,
+    collections::BTreeMap,
     fs::{self, File},
     io::Write,
+    path::{Path, PathBuf},
+    process::Command,
 };
 use tempfile::tempdir;
 
@@ -15,19 +27,33 @@ fn smoke_test() -> Result<()> {
     let mut file = File::create(file_path)?;
     write!(file, "-- a\n -- b\n")?;
 
-    let options = GetStatsOptions { gitignore: false };
+    let options = GetStatsOptions {
+        gitignore: false,
+        ..Default::default()
+    };
     assert_eq!(
         get_stats_parallel(dir.path(), &options)?,
         Stats {
             total_loc: 2,
             number_of_files: 1,
-            by_lang: HashMap::from([(
+            by_lang: IndexMap::from([(
                 "Haskell",
                 LangStat {
+                    files: 1,
                     loc: 2,
+                    code: 0,
+                    comments: 2,
+                    docs: 0,
+                    blanks: 0,
+                    bytes: 11,
+                    avg_loc: 2.0,
+                    max_loc: 2,
                     percent: 100.0
                 }
-            )])
+            )]),
+            excluded: BTreeMap::new(),
+            binaries: BinaryStat::default(),
+            files: Vec::new()
         }
     );
 
@@ -46,19 +72,33 @@ fn deep_dir_tree() -> Result<()> {
     path.push("main.rs");
     fs::write(path, "// wowsers\n")?;
 
-    let options = GetStatsOptions { gitignore: false };
+    let options = GetStatsOptions {
+        gitignore: false,
+        ..Default::default()
+    };
     assert_eq!(
         get_stats_parallel(dir.path(), &options)?,
         Stats {
             total_loc: 1,
             number_of_files: 1,
-            by_lang: HashMap::from([(
+            by_lang: IndexMap::from([(
                 "Rust",
                 LangStat {
+                    files: 1,
                     loc: 1,
+                    code: 0,
+                    comments: 1,
+                    docs: 0,
+                    blanks: 0,
+                    bytes: 11,
+                    avg_loc: 1.0,
+                    max_loc: 1,
                     percent: 100.0
                 }
-            )])
+            )]),
+            excluded: BTreeMap::new(),
+            binaries: BinaryStat::default(),
+            files: Vec::new()
         }
     );
 
@@ -76,19 +116,33 @@ fn one_million_loc_codebase() -> Result<()> {
         path.pop();
     }
 
-    let options = GetStatsOptions { gitignore: false };
+    let options = GetStatsOptions {
+        gitignore: false,
+        ..Default::default()
+    };
     assert_eq!(
         get_stats_parallel(dir.path(), &options)?,
         Stats {
             total_loc: 1_000_000,
             number_of_files: 100,
-            by_lang: HashMap::from([(
+            by_lang: IndexMap::from([(
                 "Brainfuck",
                 LangStat {
+                    files: 100,
                     loc: 1_000_000,
+                    code: 1_000_000,
+                    comments: 0,
+                    docs: 0,
+                    blanks: 0,
+                    bytes: 7_000_000,
+                    avg_loc: 10_000.0,
+                    max_loc: 10000,
                     percent: 100.0
                 }
-            )])
+            )]),
+            excluded: BTreeMap::new(),
+            binaries: BinaryStat::default(),
+            files: Vec::new()
         }
     );
 
@@ -108,30 +162,1349 @@ fn test_gitignore() -> Result<()> {
     )
     .context("Could not write text file")?;
 
-    let options = GetStatsOptions { gitignore: true };
+    let options = GetStatsOptions {
+        gitignore: true,
+        ..Default::default()
+    };
     assert_eq!(
         get_stats_parallel(dir.path(), &options)?,
         Stats {
             total_loc: 4,
             number_of_files: 2,
-            by_lang: HashMap::from([
+            by_lang: IndexMap::from([
                 (
                     "Haskell",
                     LangStat {
+                        files: 1,
                         loc: 2,
+                        code: 0,
+                        comments: 2,
+                        docs: 0,
+                        blanks: 0,
+                        bytes: 10,
+                        avg_loc: 2.0,
+                        max_loc: 2,
                         percent: 50.0
                     }
                 ),
                 (
                     "JavaScript",
                     LangStat {
+                        files: 1,
                         loc: 2,
+                        code: 0,
+                        comments: 2,
+                        docs: 0,
+                        blanks: 0,
+                        bytes: 10,
+                        avg_loc: 2.0,
+                        max_loc: 2,
                         percent: 50.0
                     }
                 )
-            ])
+            ]),
+            excluded: BTreeMap::new(),
+            binaries: BinaryStat::default(),
+            files: Vec::new()
+        }
+    );
+
+    Ok(())
+}
+
+#[test]
+fn code_comments_and_blanks() -> Result<()> {
+    let dir = tempdir()?;
+
+    fs::write(
+        dir.path().join("main.rs"),
+        "// header\n\nfn main() {\n    /* multi\n       line */\n    let x = 1; // trailing\n}\n",
+    )?;
+    fs::write(dir.path().join("script.py"), "# comment\nprint(1)\n\n")?;
+
+    let options = GetStatsOptions {
+        gitignore: false,
+        ..Default::default()
+    };
+    let stats = get_stats_parallel(dir.path(), &options)?;
+
+    assert_eq!(
+        stats.by_lang["Rust"],
+        LangStat {
+            files: 1,
+            loc: 7,
+            code: 3,
+            comments: 3,
+            docs: 0,
+            blanks: 1,
+            bytes: 80,
+            avg_loc: 7.0,
+            max_loc: 7,
+            percent: 70.0
+        }
+    );
+    assert_eq!(
+        stats.by_lang["Python"],
+        LangStat {
+            files: 1,
+            loc: 3,
+            code: 1,
+            comments: 1,
+            docs: 0,
+            blanks: 1,
+            bytes: 20,
+            avg_loc: 3.0,
+            max_loc: 3,
+            percent: 30.0
+        }
+    );
+
+    Ok(())
+}
+
+#[test]
+fn nested_block_comments() -> Result<()> {
+    let dir = tempdir()?;
+
+    // Rust block comments nest, so the last line is still a comment
+    fs::write(
+        dir.path().join("nested.rs"),
+        "/* outer\n/* inner */\nstill comment */\nfn main() {}\n",
+    )?;
+    // C block comments do not nest, so the first `*/` closes the comment
+    fs::write(
+        dir.path().join("flat.c"),
+        "/* outer\n/* inner */\nint x; */\n",
+    )?;
+
+    let options = GetStatsOptions {
+        gitignore: false,
+        ..Default::default()
+    };
+    let stats = get_stats_parallel(dir.path(), &options)?;
+
+    assert_eq!(stats.by_lang["Rust"].comments, 3);
+    assert_eq!(stats.by_lang["Rust"].code, 1);
+    assert_eq!(stats.by_lang["C"].comments, 2);
+    assert_eq!(stats.by_lang["C"].code, 1);
+
+    Ok(())
+}
+
+#[test]
+fn comment_markers_inside_strings() -> Result<()> {
+    let dir = tempdir()?;
+
+    fs::write(
+        dir.path().join("main.rs"),
+        "let a = \"// not a comment\";\nlet b = r#\"/* not a block\"#;\nlet c = 1;\n",
+    )?;
+    fs::write(
+        dir.path().join("main.py"),
+        "x = '# not a comment'\ny = \"\"\"\n# still a string\n\"\"\"\n# a comment\n",
+    )?;
+    fs::write(dir.path().join("main.js"), "const s = `/*\n*/`;\n")?;
+
+    let options = GetStatsOptions {
+        gitignore: false,
+        ..Default::default()
+    };
+    let stats = get_stats_parallel(dir.path(), &options)?;
+
+    assert_eq!(stats.by_lang["Rust"].code, 3);
+    assert_eq!(stats.by_lang["Rust"].comments, 0);
+    assert_eq!(stats.by_lang["Python"].code, 4);
+    assert_eq!(stats.by_lang["Python"].comments, 1);
+    assert_eq!(stats.by_lang["JavaScript"].code, 2);
+    assert_eq!(stats.by_lang["JavaScript"].comments, 0);
+
+    Ok(())
+}
+
+#[test]
+fn stray_quotes_end_with_their_line() -> Result<()> {
+    let dir = tempdir()?;
+
+    fs::write(
+        dir.path().join("main.c"),
+        "#error don't\n// a comment\n/* another one */\nint x;\n",
+    )?;
+    fs::write(
+        dir.path().join("run.sh"),
+        "echo Don't\n# a comment\n# another one\n",
+    )?;
+    fs::write(
+        dir.path().join("query.sql"),
+        "SELECT 'it''s\n-- a comment\n",
+    )?;
+
+    let options = GetStatsOptions {
+        gitignore: false,
+        ..Default::default()
+    };
+    let stats = get_stats_parallel(dir.path(), &options)?;
+
+    assert_eq!(stats.by_lang["C"].code, 2);
+    assert_eq!(stats.by_lang["C"].comments, 2);
+    assert_eq!(stats.by_lang["Shell"].code, 1);
+    assert_eq!(stats.by_lang["Shell"].comments, 2);
+    assert_eq!(stats.by_lang["SQLPL"].code, 1);
+    assert_eq!(stats.by_lang["SQLPL"].comments, 1);
+
+    Ok(())
+}
+
+#[test]
+fn rust_char_literals_are_not_strings() -> Result<()> {
+    let dir = tempdir()?;
+
+    fs::write(
+        dir.path().join("main.rs"),
+        "let q = '\"';\nlet b = b'\"';\nlet e = '\\'';\nfn f<'a>(s: &'a str) -> &'a str { s }\n// a comment\n// another one\n",
+    )?;
+
+    let options = GetStatsOptions {
+        gitignore: false,
+        ..Default::default()
+    };
+    let stats = get_stats_parallel(dir.path(), &options)?;
+
+    assert_eq!(stats.by_lang["Rust"].code, 4);
+    assert_eq!(stats.by_lang["Rust"].comments, 2);
+
+    Ok(())
+}
+
+#[test]
+fn docs_are_counted_separately() -> Result<()> {
+    let dir = tempdir()?;
+
+    fs::write(
+        dir.path().join("lib.rs"),
+        "//! Crate docs\n/// Function docs\n/** Block docs */\n/**/\n// comment\n//// not docs\n/*** not docs */\nfn f() {}\n",
+    )?;
+    fs::write(
+        dir.path().join("mod.py"),
+        "\"\"\"Module docs.\"\"\"\n\"\"\"not docs\"\"\"\n\ndef f(\n    a: int = 1,\n):\n    \"\"\"\n    Function docs.\n    \"\"\"\n    x = \"\"\"not docs\"\"\"\n    if a:\n        \"\"\"not docs\"\"\"\n",
+    )?;
+    fs::write(
+        dir.path().join("mod.ex"),
+        "defmodule M do\n  @moduledoc \"\"\"\n  Module docs.\n  \"\"\"\n  @doc false\n  def f, do: 1\nend\n",
+    )?;
+
+    let options = GetStatsOptions {
+        gitignore: false,
+        ..Default::default()
+    };
+    let stats = get_stats_parallel(dir.path(), &options)?;
+
+    let rust = &stats.by_lang["Rust"];
+    assert_eq!((rust.code, rust.comments, rust.docs), (1, 4, 3));
+    let python = &stats.by_lang["Python"];
+    assert_eq!((python.code, python.comments, python.docs), (7, 0, 4));
+    let elixir = &stats.by_lang["Elixir"];
+    assert_eq!((elixir.code, elixir.comments, elixir.docs), (4, 0, 3));
+
+    Ok(())
+}
+
+#[test]
+fn detects_languages_by_filename() -> Result<()> {
+    let dir = tempdir()?;
+
+    fs::write(dir.path().join("Makefile"), "all:\n\techo hi\n")?;
+    fs::write(dir.path().join("Dockerfile.dev"), "FROM rust\n")?;
+    fs::write(dir.path().join("Jenkinsfile"), "pipeline {}\n")?;
+    fs::write(dir.path().join("CMakeLists.txt"), "project(x)\n")?;
+    fs::write(dir.path().join(".bashrc"), "# bashrc\nalias ll='ls -l'\n")?;
+
+    let options = GetStatsOptions {
+        gitignore: false,
+        ..Default::default()
+    };
+    let stats = get_stats_parallel(dir.path(), &options)?;
+
+    assert_eq!(stats.by_lang["Makefile"].loc, 2);
+    assert_eq!(stats.by_lang["Dockerfile"].loc, 1);
+    assert_eq!(stats.by_lang["Groovy"].loc, 1);
+    assert_eq!(stats.by_lang["CMake"].loc, 1);
+    assert!(!stats.by_lang.contains_key("Other"));
+
+    // hidden files are skipped by the walker, unless given explicitly
+    let stats = get_stats_parallel(&dir.path().join(".bashrc"), &options)?;
+    assert_eq!(stats.by_lang["Shell"].comments, 1);
+
+    Ok(())
+}
+
+#[test]
+fn detects_extensionless_scripts_by_shebang() -> Result<()> {
+    let dir = tempdir()?;
+
+    fs::write(
+        dir.path().join("run"),
+        "#!/usr/bin/env python3.11\nprint(1)\n",
+    )?;
+    fs::write(dir.path().join("build"), "#!/bin/bash\necho hi\n")?;
+    fs::write(
+        dir.path().join("serve"),
+        "#!/usr/bin/env -S node --harmony\n",
+    )?;
+    fs::write(
+        dir.path().join("clean"),
+        "#!/usr/bin/env -u PYTHONPATH python3\n",
+    )?;
+    fs::write(dir.path().join("grammar"), "#!/usr/bin/env perl6\n")?;
+    fs::write(dir.path().join("notes"), "just some text\n")?;
+
+    let options = GetStatsOptions {
+        gitignore: false,
+        ..Default::default()
+    };
+    let stats = get_stats_parallel(dir.path(), &options)?;
+
+    assert_eq!(stats.by_lang["Python"].loc, 3);
+    assert_eq!(stats.by_lang["Shell"].loc, 2);
+    assert_eq!(stats.by_lang["JavaScript"].loc, 1);
+    assert_eq!(stats.by_lang["Perl6"].loc, 1);
+    assert_eq!(stats.by_lang["Other"].loc, 1);
+
+    Ok(())
+}
+
+fn lang_of(file_name: &str, contents: &str) -> Result<String> {
+    let dir = tempdir()?;
+    fs::write(dir.path().join(file_name), contents)?;
+
+    let options = GetStatsOptions {
+        gitignore: false,
+        ..Default::default()
+    };
+    let stats = get_stats_parallel(dir.path(), &options)?;
+    let lang = stats.by_lang.keys().next().context("No files counted")?;
+
+    Ok(lang.to_string())
+}
+
+#[test]
+fn disambiguates_h_files() -> Result<()> {
+    assert_eq!(lang_of("a.h", "#include <stdio.h>\nint f(void);\n")?, "C");
+    assert_eq!(lang_of("a.h", "#include <vector>\nclass A {};\n")?, "C++");
+    assert_eq!(
+        lang_of(
+            "a.h",
+            "#import <Foundation/Foundation.h>\n@interface A\n@end\n"
+        )?,
+        "Objective-C"
+    );
+    Ok(())
+}
+
+#[test]
+fn disambiguates_m_files() -> Result<()> {
+    assert_eq!(
+        lang_of("a.m", "#import \"a.h\"\n@implementation A\n@end\n")?,
+        "Objective-C"
+    );
+    assert_eq!(
+        lang_of("a.m", "% compute\nfunction y = f(x)\n  y = x;\nend\n")?,
+        "Matlab"
+    );
+    Ok(())
+}
+
+#[test]
+fn disambiguates_pl_files() -> Result<()> {
+    assert_eq!(lang_of("a.pl", "use strict;\nmy $x = 1;\n")?, "Perl");
+    assert_eq!(
+        lang_of("a.pl", "parent(a, b).\nanc(X, Y) :- parent(X, Y).\n")?,
+        "Prolog"
+    );
+    Ok(())
+}
+
+#[test]
+fn disambiguates_r_files() -> Result<()> {
+    assert_eq!(lang_of("a.r", "x <- c(1, 2)\nlibrary(ggplot2)\n")?, "R");
+    assert_eq!(lang_of("a.r", "REBOL [Title: \"x\"]\nprint 1\n")?, "Rebol");
+    Ok(())
+}
+
+#[test]
+fn disambiguates_inc_files() -> Result<()> {
+    assert_eq!(lang_of("a.inc", "<?php\necho 1;\n")?, "PHP");
+    assert_eq!(
+        lang_of(
+            "a.inc",
+            "#pragma semicolon 1\npublic void OnPluginStart() {}\n"
+        )?,
+        "SourcePawn"
+    );
+    assert_eq!(lang_of("a.inc", "%macro PUSH 0\n%endmacro\n")?, "Assembly");
+    assert_eq!(
+        lang_of("a.inc", "#define TABLE_SIZE 4\n{1, 2, 3, 4},\n")?,
+        "C"
+    );
+    Ok(())
+}
+
+#[test]
+fn disambiguates_mod_files() -> Result<()> {
+    assert_eq!(lang_of("a.mod", "<?xml version=\"1.0\"?>\n<a/>\n")?, "XML");
+    assert_eq!(
+        lang_of("go.mod", "module example.com/x\n\ngo 1.21\n")?,
+        "Go Module"
+    );
+    assert_eq!(lang_of("a.mod", "MODULE Hello;\nEND Hello.\n")?, "Modula-2");
+    assert_eq!(lang_of("a.mod", "var x >= 0;\nmaximize z: x;\n")?, "AMPL");
+    Ok(())
+}
+
+#[test]
+fn disambiguates_d_files() -> Result<()> {
+    assert_eq!(lang_of("a.d", "import std.stdio;\nvoid main() {}\n")?, "D");
+    assert_eq!(
+        lang_of("a.d", "syscall::open:entry { trace(0); }\n")?,
+        "DTrace"
+    );
+    assert_eq!(lang_of("a.d", "main.o: main.c \\\n  util.h\n")?, "Makefile");
+    Ok(())
+}
+
+#[test]
+fn heuristics_from_config_come_first() -> Result<()> {
+    let dir = tempdir()?;
+
+    fs::write(
+        dir.path().join(".loc-stats.toml"),
+        r#"
+[heuristics]
+h = [{ language = "c++", pattern = '(?m)^\s*BEGIN_CPP_API\b' }]
+"#,
+    )?;
+    fs::write(
+        dir.path().join("api.h"),
+        "BEGIN_CPP_API
+int f(void);
+",
+    )?;
+    fs::write(
+        dir.path().join("util.h"),
+        "#include <stdio.h>
+int g(void);
+",
+    )?;
+
+    let config = Config::discover(dir.path())?.context("Config not found")?;
+    let stats = get_stats_parallel(dir.path(), &config.stats_options()?)?;
+
+    assert_eq!(stats.by_lang["C++"].loc, 2);
+    // the built-in heuristics still apply when none from the config matches
+    assert_eq!(stats.by_lang["C"].loc, 2);
+
+    let config: Config = toml::from_str("[heuristics]\nh = [{ language = \"C\", pattern = '(' }]")?;
+    assert!(config.stats_options().is_err());
+
+    Ok(())
+}
+
+#[test]
+fn modelines_override_detection() -> Result<()> {
+    let dir = tempdir()?;
+
+    fs::write(
+        dir.path().join("script"),
+        "print(1)\n# vim: set ft=python:\n",
+    )?;
+    fs::write(
+        dir.path().join("tool.txt"),
+        "# -*- mode: ruby -*-\nputs 1\n",
+    )?;
+    // only the first and last lines of a file are searched
+    fs::write(
+        dir.path().join("long.txt"),
+        "puts 1\n".repeat(20) + "# vim: set ft=ruby:\n",
+    )?;
+    fs::write(
+        dir.path().join("middle.txt"),
+        "x\n".repeat(10) + "# vim: set ft=ruby:\n" + &"x\n".repeat(10),
+    )?;
+
+    let options = GetStatsOptions {
+        gitignore: false,
+        ..Default::default()
+    };
+    let stats = get_stats_parallel(dir.path(), &options)?;
+
+    assert_eq!(stats.by_lang["Python"].loc, 2);
+    assert_eq!(stats.by_lang["Ruby"].loc, 23);
+
+    Ok(())
+}
+
+#[test]
+fn gitattributes_linguist_overrides() -> Result<()> {
+    let dir = tempdir()?;
+
+    fs::write(
+        dir.path().join(".gitattributes"),
+        "*.inc linguist-language=PHP\nvendor/** linguist-vendored\n*.pb.go linguist-generated=true\ndocs/** linguist-documentation\n",
+    )?;
+    fs::write(dir.path().join("header.inc"), "echo 1;\n")?;
+    fs::create_dir(dir.path().join("vendor"))?;
+    fs::write(dir.path().join("vendor/lib.js"), "a();\nb();\n")?;
+    fs::write(dir.path().join("api.pb.go"), "package api\n")?;
+    fs::create_dir(dir.path().join("docs"))?;
+    fs::write(dir.path().join("docs/guide.md"), "# Guide\n")?;
+    fs::write(dir.path().join("main.go"), "package main\n")?;
+
+    let options = GetStatsOptions {
+        gitignore: false,
+        ..Default::default()
+    };
+    let stats = get_stats_parallel(dir.path(), &options)?;
+
+    assert_eq!(stats.by_lang["PHP"].loc, 1);
+    assert_eq!(stats.by_lang["Go"].loc, 1);
+    assert!(!stats.by_lang.contains_key("JavaScript"));
+    assert!(!stats.by_lang.contains_key("Markdown"));
+    assert_eq!(
+        stats.excluded["vendored"],
+        ExcludedStat { files: 1, loc: 2 }
+    );
+    assert_eq!(
+        stats.excluded["generated"],
+        ExcludedStat { files: 1, loc: 1 }
+    );
+    assert_eq!(
+        stats.excluded["documentation"],
+        ExcludedStat { files: 1, loc: 1 }
+    );
+
+    Ok(())
+}
+
+#[test]
+fn binary_files_are_skipped() -> Result<()> {
+    let dir = tempdir()?;
+
+    fs::write(dir.path().join("image.png"), b"\x89PNG\r\n\x1a\n\x00\x00\n")?;
+    fs::write(dir.path().join("blob"), b"\xff\xfe\n\xc3\x28\n")?;
+    fs::write(dir.path().join("main.rs"), "fn main() {}\n")?;
+    // files bigger than the block inspected for binary content
+    let mut database = b"SQLite\x00".to_vec();
+    database.resize(20_000, b'x');
+    fs::write(dir.path().join("data.db"), database)?;
+    fs::write(dir.path().join("words.txt"), "word\n".repeat(5_000))?;
+
+    let options = GetStatsOptions {
+        gitignore: false,
+        ..Default::default()
+    };
+    let stats = get_stats_parallel(dir.path(), &options)?;
+
+    assert_eq!(stats.total_loc, 5_001);
+    assert_eq!(stats.number_of_files, 2);
+    assert_eq!(
+        stats.binaries,
+        BinaryStat {
+            files: 3,
+            bytes: 20_017
         }
     );
 
     Ok(())
 }
+
+#[test]
+fn include_and_exclude_globs() -> Result<()> {
+    let dir = tempdir()?;
+
+    fs::create_dir_all(dir.path().join("src/vendor"))?;
+    fs::write(dir.path().join("src/main.rs"), "fn main() {}\n")?;
+    fs::write(dir.path().join("src/api.generated.ts"), "export {};\n")?;
+    fs::write(dir.path().join("src/vendor/lib.rs"), "fn f() {}\n")?;
+    fs::write(dir.path().join("build.rs"), "fn main() {}\n")?;
+    fs::create_dir(dir.path().join("vendor"))?;
+    fs::write(dir.path().join("vendor/dep.rs"), "fn g() {}\n")?;
+
+    let options = GetStatsOptions {
+        gitignore: false,
+        include: vec!["src/**".to_string()],
+        exclude: vec!["vendor/".to_string(), "*.generated.ts".to_string()],
+        ..Default::default()
+    };
+    let stats = get_stats_parallel(dir.path(), &options)?;
+
+    assert_eq!(stats.number_of_files, 1);
+    assert_eq!(stats.by_lang["Rust"].loc, 1);
+    assert!(!stats.by_lang.contains_key("TypeScript"));
+
+    // a glob with a slash is anchored to the counted path
+    let options = GetStatsOptions {
+        gitignore: false,
+        exclude: vec!["vendor/**".to_string()],
+        files: true,
+        ..Default::default()
+    };
+    let stats = get_stats_parallel(dir.path(), &options)?;
+    let mut paths: Vec<_> = stats
+        .files
+        .iter()
+        .map(|file| file.path.strip_prefix(dir.path()).unwrap())
+        .collect();
+    paths.sort();
+    assert_eq!(
+        paths,
+        [
+            Path::new("build.rs"),
+            Path::new("src/api.generated.ts"),
+            Path::new("src/main.rs"),
+            Path::new("src/vendor/lib.rs"),
+        ]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn config_file_is_discovered_and_applied() -> Result<()> {
+    let dir = tempdir()?;
+
+    fs::write(
+        dir.path().join(".loc-stats.toml"),
+        r#"
+exclude = ["vendor/", "src/gen/**"]
+
+[extensions]
+flow = "Python"
+
+[languages]
+"scripts/*" = "Ruby"
+
+[thresholds]
+max-total-loc = 2
+max-percent = { Python = 50.0 }
+"#,
+    )?;
+    fs::create_dir_all(dir.path().join("src/vendor"))?;
+    fs::create_dir(dir.path().join("scripts"))?;
+    fs::write(dir.path().join("src/rules.flow"), "# rules\nx = 1\n")?;
+    fs::write(dir.path().join("src/vendor/lib.rs"), "fn f() {}\n")?;
+    fs::write(dir.path().join("scripts/deploy.sh"), "puts 1\n")?;
+    fs::create_dir(dir.path().join("src/gen"))?;
+    fs::write(dir.path().join("src/gen/out.py"), "y = 2\n")?;
+
+    let config = Config::discover(&dir.path().join("src"))?.context("Config not found")?;
+    let options = config.stats_options()?;
+    let stats = get_stats_parallel(dir.path(), &options)?;
+
+    assert_eq!(stats.by_lang["Python"].comments, 1);
+    assert_eq!(stats.by_lang["Python"].code, 1);
+    assert_eq!(stats.by_lang["Ruby"].code, 1);
+    assert!(!stats.by_lang.contains_key("Rust"));
+    assert_eq!(
+        config.thresholds.violations(&stats, &options.langs).len(),
+        2
+    );
+
+    // globs are relative to the config file, even when counting a subdirectory
+    let src = get_stats_parallel(&dir.path().join("src"), &options)?;
+    assert_eq!(src.by_lang["Python"].code, 1);
+    let scripts = get_stats_parallel(&dir.path().join("scripts"), &options)?;
+    assert_eq!(scripts.by_lang["Ruby"].code, 1);
+
+    Ok(())
+}
+
+#[test]
+fn thresholds_from_config_and_options() -> Result<()> {
+    let dir = tempdir()?;
+    fs::write(dir.path().join("short.rs"), "// short\nfn f() {}\n")?;
+    fs::write(dir.path().join("long.rs"), "fn f() {}\n".repeat(5))?;
+    fs::write(dir.path().join("app.js"), "f();\n")?;
+
+    let options = GetStatsOptions {
+        files: true,
+        ..Default::default()
+    };
+    let stats = get_stats_parallel(dir.path(), &options)?;
+
+    let mut thresholds: Thresholds = toml::from_str(
+        r#"
+max-total-loc = 100
+max-percent = { JavaScript = 5.0 }
+min-comment-ratio = 0.5
+"#,
+    )?;
+    thresholds.merge(Thresholds {
+        max_file_loc: Some(4),
+        min_comment_ratio: Some(0.1),
+        ..Default::default()
+    });
+    assert_eq!(thresholds.max_total_loc, Some(100));
+    assert_eq!(thresholds.min_comment_ratio, Some(0.1));
+
+    let long = dir.path().join("long.rs");
+    assert_eq!(
+        thresholds.violations(&stats, &options.langs),
+        vec![
+            "JavaScript: 12.5% of lines exceeds the limit of 5%".to_string(),
+            format!("{}: 5 lines exceeds the limit of 4", long.display()),
+        ]
+    );
+    assert!(Thresholds::default().is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn custom_languages_from_config_and_registry() -> Result<()> {
+    let dir = tempdir()?;
+
+    fs::write(
+        dir.path().join(".loc-stats.toml"),
+        r#"
+[extensions]
+inc = "PHP"
+
+[custom-languages.Flow]
+extensions = ["flow"]
+line-comments = ["--"]
+block-comments = [["{-", "-}"]]
+nested = true
+quotes = [['"', '"']]
+doc-comments = ["--|"]
+
+[thresholds]
+max-percent = { flow = 50.0, php = 33.333 }
+"#,
+    )?;
+    fs::write(
+        dir.path().join("rules.flow"),
+        "--| Routes orders.\n{- outer {- inner -} still -}\nroute \"--\" -> ship\n\n",
+    )?;
+    fs::write(dir.path().join("header.inc"), "<?php\n// helpers\n")?;
+
+    let config = Config::discover(dir.path())?.context("Config not found")?;
+    let options = config.stats_options()?;
+    let stats = get_stats_parallel(dir.path(), &options)?;
+
+    let flow = &stats.by_lang["Flow"];
+    assert_eq!(
+        (flow.code, flow.comments, flow.docs, flow.blanks),
+        (1, 1, 1, 1)
+    );
+    assert_eq!(stats.by_lang["PHP"].comments, 1);
+    // custom languages can be limited too, and shares are not rounded down
+    assert_eq!(
+        config.thresholds.violations(&stats, &options.langs),
+        [
+            "Flow: 66.67% of lines exceeds the limit of 50%",
+            "PHP: 33.34% of lines exceeds the limit of 33.333%",
+        ]
+    );
+
+    let mut langs = LangRegistry::new();
+    langs.add_language(
+        "Flow",
+        LangSyntax {
+            line_comments: &["--"],
+            block_comments: &[],
+            nested: false,
+            nested_comments: &[],
+            quotes: &[],
+            verbatim_quotes: &[],
+            char_literals: false,
+            docs: NO_DOCS,
+        },
+    );
+    langs.add_extension("flow", "flow")?;
+    let options = GetStatsOptions {
+        langs,
+        ..Default::default()
+    };
+    let stats = get_stats_parallel(&dir.path().join("rules.flow"), &options)?;
+    assert_eq!(stats.by_lang["Flow"].comments, 1);
+    assert_eq!(stats.by_lang["Flow"].code, 2);
+
+    Ok(())
+}
+
+#[test]
+fn multiple_paths_are_combined() -> Result<()> {
+    let dir = tempdir()?;
+
+    fs::create_dir_all(dir.path().join("api/src"))?;
+    fs::create_dir(dir.path().join("web"))?;
+    fs::write(dir.path().join("api/src/main.rs"), "fn main() {}\n")?;
+    fs::write(dir.path().join("web/app.js"), "run();\n// start\n")?;
+
+    let paths = [
+        dir.path().join("api/src"),
+        dir.path().join("web"),
+        dir.path().join("api"),
+        dir.path().join("web"),
+    ];
+    assert_eq!(
+        dedup_roots(&paths),
+        [dir.path().join("web"), dir.path().join("api")]
+    );
+
+    let options = GetStatsOptions {
+        gitignore: false,
+        ..Default::default()
+    };
+    let roots = get_stats_roots(&paths, &options)?;
+    assert_eq!(roots.len(), 2);
+    assert_eq!(roots[0].stats.total_loc, 2);
+    assert_eq!(roots[1].stats.total_loc, 1);
+
+    let stats = Stats::combine(roots.iter().map(|root| &root.stats));
+    assert_eq!(stats.total_loc, 3);
+    assert_eq!(stats.number_of_files, 2);
+    assert_eq!(stats.by_lang["JavaScript"].percent, 66.66);
+    assert_eq!(stats.by_lang["Rust"].percent, 33.33);
+
+    Ok(())
+}
+
+#[test]
+fn files_are_listed_when_asked() -> Result<()> {
+    let dir = tempdir()?;
+
+    fs::create_dir(dir.path().join("src"))?;
+    fs::write(dir.path().join("src/main.rs"), "fn main() {}\n\n")?;
+    fs::write(dir.path().join("build.py"), "# build\nrun()\n")?;
+    fs::write(dir.path().join("logo.png"), [0x89, b'P', b'N', b'G', 0, 0])?;
+
+    let options = GetStatsOptions {
+        gitignore: false,
+        files: true,
+        ..Default::default()
+    };
+    let stats = get_stats_parallel(dir.path(), &options)?;
+
+    assert_eq!(
+        stats.files,
+        [
+            FileStat {
+                path: dir.path().join("build.py"),
+                lang: "Python",
+                loc: 2,
+                code: 1,
+                comments: 1,
+                docs: 0,
+                blanks: 0,
+                bytes: 14
+            },
+            FileStat {
+                path: dir.path().join("src/main.rs"),
+                lang: "Rust",
+                loc: 2,
+                code: 1,
+                comments: 0,
+                docs: 0,
+                blanks: 1,
+                bytes: 14
+            }
+        ]
+    );
+
+    let options = GetStatsOptions {
+        gitignore: false,
+        ..Default::default()
+    };
+    assert!(get_stats_parallel(dir.path(), &options)?.files.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn lines_roll_up_directories() -> Result<()> {
+    let dir = tempdir()?;
+
+    fs::create_dir_all(dir.path().join("src/parser/lexer"))?;
+    fs::write(dir.path().join("build.py"), "run()\n")?;
+    fs::write(dir.path().join("src/main.rs"), "fn main() {}\n")?;
+    fs::write(dir.path().join("src/parser/mod.rs"), "mod lexer;\n\n")?;
+    fs::write(dir.path().join("src/parser/lexer/mod.rs"), "// TODO\n")?;
+
+    let options = GetStatsOptions {
+        gitignore: false,
+        files: true,
+        ..Default::default()
+    };
+    let stats = get_stats_parallel(dir.path(), &options)?;
+
+    let tree = DirStat::from_files(dir.path(), &stats.files, None);
+    assert_eq!((tree.loc, tree.files, tree.lang), (5, 4, Some("Rust")));
+    let src = &tree.dirs["src"];
+    assert_eq!((src.loc, src.files), (4, 3));
+    assert_eq!(src.dirs["parser"].dirs["lexer"].loc, 1);
+
+    let tree = DirStat::from_files(dir.path(), &stats.files, Some(1));
+    assert_eq!(tree.dirs["src"].loc, 4);
+    assert!(tree.dirs["src"].dirs.is_empty());
+    assert_eq!(tree.by_lang["Python"], 1);
+
+    Ok(())
+}
+
+#[test]
+fn csv_and_tsv_output() -> Result<()> {
+    let dir = tempdir()?;
+
+    fs::write(dir.path().join("a,\"b\".rs"), "fn main() {}\n// hi\n\n")?;
+    fs::write(dir.path().join("tab\there.py"), "x = 1\n\n")?;
+
+    let options = GetStatsOptions {
+        gitignore: false,
+        files: true,
+        ..Default::default()
+    };
+    let mut stats = get_stats_parallel(dir.path(), &options)?;
+    for file in &mut stats.files {
+        file.path = file.path.strip_prefix(dir.path())?.to_path_buf();
+    }
+
+    assert_eq!(
+        stats.to_delimited(',', false),
+        "language,files,lines,code,comments,docs,blanks,bytes,avg_lines,max_lines,percent\n\
+         Rust,1,3,1,1,0,1,20,3,3,60\n\
+         Python,1,2,1,0,0,1,7,2,2,40\n"
+    );
+    assert_eq!(
+        stats.to_delimited(',', true),
+        "path,language,lines,code,comments,docs,blanks,bytes\n\
+         \"a,\"\"b\"\".rs\",Rust,3,1,1,0,1,20\n\
+         tab\there.py,Python,2,1,0,0,1,7\n"
+    );
+    assert_eq!(
+        stats.to_delimited('\t', true),
+        "path\tlanguage\tlines\tcode\tcomments\tdocs\tblanks\tbytes\n\
+         a,\"b\".rs\tRust\t3\t1\t1\t0\t1\t20\n\
+         tab\\there.py\tPython\t2\t1\t0\t0\t1\t7\n"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn markdown_output() -> Result<()> {
+    let dir = tempdir()?;
+
+    fs::write(dir.path().join("main.rs"), "fn main() {}\n\n\n")?;
+    fs::write(dir.path().join("lib.rs"), "mod a;\n")?;
+    fs::write(dir.path().join("build.py"), "x = 1\n")?;
+
+    let options = GetStatsOptions {
+        gitignore: false,
+        ..Default::default()
+    };
+    let stats = get_stats_parallel(dir.path(), &options)?;
+
+    assert_eq!(
+        stats.to_markdown(),
+        "\
+| Language  | Files | Lines |       % |
+| --------- | ----: | ----: | ------: |
+| Rust      |     2 |     4 |      80 |
+| Python    |     1 |     1 |      20 |
+| **Total** | **3** | **5** | **100** |
+"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn html_report_is_self_contained() -> Result<()> {
+    let dir = tempdir()?;
+
+    fs::create_dir(dir.path().join("src"))?;
+    fs::write(
+        dir.path().join("src/<main>.rs"),
+        "fn main() {}\nfn f() {}\n",
+    )?;
+    fs::write(dir.path().join("build.py"), "x = 1\n")?;
+
+    let options = GetStatsOptions {
+        gitignore: false,
+        files: true,
+        ..Default::default()
+    };
+    let stats = get_stats_parallel(dir.path(), &options)?;
+    let dirs = BTreeMap::from([(
+        "project".to_string(),
+        DirStat::from_files(dir.path(), &stats.files, None),
+    )]);
+    let html = stats.to_html(&dirs);
+
+    assert!(html.starts_with("<!DOCTYPE html>"));
+    assert_eq!(html.matches("<svg").count(), 2);
+    // one slice per language in the pie chart
+    assert_eq!(html.matches("<path").count(), 2);
+    // one rectangle per directory in the treemap
+    assert_eq!(html.matches("<rect").count(), 2);
+    assert!(html.contains("src/&lt;main&gt;.rs</td>"));
+    assert!(html.contains("<th class=\"num\" data-col=\"2\">Lines</th>"));
+    assert!(!html.contains("src=") && !html.contains("href="));
+
+    // colors follow the share of lines, not the order of the table
+    let mut stats = stats;
+    for sort in [SortOrder::Lines, SortOrder::Name] {
+        stats.sort(sort);
+        let html = stats.to_html(&dirs);
+        assert!(html.contains("background:#4e79a7\"></span>Rust"));
+        assert!(html.contains("background:#f28e2b\"></span>Python"));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn svg_badges() -> Result<()> {
+    assert_eq!(human_count(0), "0");
+    assert_eq!(human_count(999), "999");
+    assert_eq!(human_count(1_000), "1k");
+    assert_eq!(human_count(12_345), "12.3k");
+    assert_eq!(human_count(123_456), "123k");
+    assert_eq!(human_count(999_950), "1M");
+    assert_eq!(human_count(4_560_000), "4.6M");
+
+    let dir = tempdir()?;
+    fs::write(dir.path().join("main.rs"), "fn main() {}\n".repeat(12_345))?;
+    fs::write(dir.path().join("a&b.py"), "x = 1\n".repeat(100))?;
+
+    let options = GetStatsOptions {
+        gitignore: false,
+        ..Default::default()
+    };
+    let stats = get_stats_parallel(dir.path(), &options)?;
+
+    let badge = stats.to_svg_badge();
+    assert!(badge.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\""));
+    assert!(badge.contains("<title>lines of code: 12.4k</title>"));
+
+    let badge = stats.to_svg_languages_badge();
+    assert!(badge.contains("<title>Rust 99.19%</title>"));
+    assert!(badge.contains("<title>Python 0.8%</title>"));
+
+    Ok(())
+}
+
+#[test]
+fn output_order_is_deterministic() -> Result<()> {
+    let dir = tempdir()?;
+
+    fs::create_dir_all(dir.path().join("b/deep"))?;
+    fs::create_dir(dir.path().join("a"))?;
+    fs::write(dir.path().join("b/deep/main.rs"), "fn main() {}\n")?;
+    fs::write(dir.path().join("a/app.js"), "run();\nstop();\n")?;
+    fs::write(dir.path().join("a/lib.c"), "int x;\nint y;\n")?;
+    fs::write(dir.path().join("a/Main.hs"), "main = 1\n".repeat(3))?;
+
+    let options = GetStatsOptions {
+        gitignore: false,
+        files: true,
+        ..Default::default()
+    };
+    let mut stats = get_stats_parallel(dir.path(), &options)?;
+
+    let langs = |stats: &Stats| stats.by_lang.keys().copied().collect::<Vec<_>>();
+    let files = |stats: &Stats| {
+        stats
+            .files
+            .iter()
+            .map(|file| file.path.strip_prefix(dir.path()).unwrap().to_path_buf())
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(langs(&stats), ["Haskell", "C", "JavaScript", "Rust"]);
+    assert_eq!(
+        files(&stats),
+        ["a/Main.hs", "a/app.js", "a/lib.c", "b/deep/main.rs"].map(PathBuf::from)
+    );
+    let json = serde_json::to_string(&stats)?;
+    let haskell = json.find("\"Haskell\"").context("No Haskell")?;
+    let c = json.find("\"C\"").context("No C")?;
+    assert!(haskell < c);
+
+    stats.sort(SortOrder::Name);
+    assert_eq!(langs(&stats), ["C", "Haskell", "JavaScript", "Rust"]);
+
+    let mut tree = DirStat::from_files(dir.path(), &stats.files, None);
+    assert_eq!(tree.dirs.keys().collect::<Vec<_>>(), ["a", "b"]);
+    fs::write(
+        dir.path().join("b/deep/main.rs"),
+        "fn main() {}\n".repeat(10),
+    )?;
+    let stats = get_stats_parallel(dir.path(), &options)?;
+    tree = DirStat::from_files(dir.path(), &stats.files, None);
+    assert_eq!(tree.dirs.keys().collect::<Vec<_>>(), ["b", "a"]);
+    tree.sort(SortOrder::Name);
+    assert_eq!(tree.dirs.keys().collect::<Vec<_>>(), ["a", "b"]);
+
+    Ok(())
+}
+
+#[test]
+fn json_sections_keep_the_language_order() -> Result<()> {
+    let dir = tempdir()?;
+    fs::write(dir.path().join("lib.c"), "int x;\n")?;
+    fs::write(dir.path().join("Main.hs"), "main = 1\n".repeat(3))?;
+
+    let options = GetStatsOptions {
+        gitignore: false,
+        files: true,
+        ..Default::default()
+    };
+    let roots = get_stats_roots(&[dir.path().to_path_buf()], &options)?;
+    let stats = Stats::combine(roots.iter().map(|root| &root.stats));
+    let dirs = BTreeMap::from([(
+        "root".to_string(),
+        DirStat::from_files(dir.path(), &stats.files, None),
+    )]);
+    let report = JsonReport {
+        stats: &stats,
+        roots: Some(&roots),
+        dirs: Some(&dirs),
+        comparison: None,
+    };
+
+    let json = serde_json::to_string(&report)?;
+    let by_lang = json.find("\"by_lang\":").context("No by_lang")?;
+    assert!(json[by_lang..].starts_with("\"by_lang\":{\"Haskell\""));
+    assert!(json.contains("\"roots\":[") && json.contains("\"dirs\":{"));
+    assert!(!json.contains("\"comparison\""));
+
+    Ok(())
+}
+
+#[test]
+fn per_language_file_stats() -> Result<()> {
+    let dir = tempdir()?;
+
+    fs::write(dir.path().join("a.yaml"), "a: 1\n".repeat(10))?;
+    fs::write(dir.path().join("b.yaml"), "b: 2\n")?;
+    fs::write(dir.path().join("c.yaml"), "c: 3\nd: 4\n")?;
+
+    let options = GetStatsOptions {
+        gitignore: false,
+        ..Default::default()
+    };
+    let stats = get_stats_parallel(dir.path(), &options)?;
+
+    let yaml = &stats.by_lang["YAML"];
+    assert_eq!(yaml.files, 3);
+    assert_eq!(yaml.bytes, 65);
+    assert_eq!(yaml.max_loc, 10);
+    assert_eq!(yaml.avg_loc, 4.33);
+
+    let combined = Stats::combine([&stats, &stats]);
+    assert_eq!(combined.by_lang["YAML"].files, 6);
+    assert_eq!(combined.by_lang["YAML"].max_loc, 10);
+    assert_eq!(combined.by_lang["YAML"].avg_loc, 4.33);
+
+    Ok(())
+}
+
+#[test]
+fn compare_against_baseline() -> Result<()> {
+    let dir = tempdir()?;
+    let baseline_file = tempfile::NamedTempFile::new()?;
+
+    fs::write(dir.path().join("main.rs"), "fn main() {}\n")?;
+    fs::write(dir.path().join("build.py"), "x = 1\ny = 2\n")?;
+    fs::write(dir.path().join("same.c"), "int x;\n")?;
+
+    let options = GetStatsOptions {
+        gitignore: false,
+        files: true,
+        ..Default::default()
+    };
+    let roots = get_stats_roots(&[dir.path().to_path_buf()], &options)?;
+    Baseline::new(&roots).save(baseline_file.path())?;
+
+    fs::write(dir.path().join("main.rs"), "fn main() {}\nfn f() {}\n\n")?;
+    fs::remove_file(dir.path().join("build.py"))?;
+    fs::write(dir.path().join("app.js"), "run();\n")?;
+    fs::write(dir.path().join("same.c"), "// int x;\n")?;
+
+    // the same files counted through another path still match
+    let roots = get_stats_roots(&[dir.path().join(".")], &options)?;
+    let after = Baseline::new(&roots);
+    let comparison = Baseline::load(baseline_file.path())?.compare(&after);
+
+    assert_eq!((comparison.before, comparison.after), (4, 5));
+    assert_eq!(comparison.delta, 1);
+    let langs: Vec<_> = comparison
+        .by_lang
+        .iter()
+        .map(|lang| (lang.lang.as_str(), lang.delta, lang.kinds.comments))
+        .collect();
+    assert_eq!(
+        langs,
+        [
+            ("Python", -2, 0),
+            ("Rust", 2, 0),
+            ("JavaScript", 1, 0),
+            ("C", 0, 1)
+        ]
+    );
+
+    let files: Vec<_> = comparison
+        .files
+        .iter()
+        .map(|file| (file.path.as_path(), file.change, file.delta))
+        .collect();
+    assert_eq!(
+        files,
+        [
+            (Path::new("app.js"), FileChange::Added, 1),
+            (Path::new("build.py"), FileChange::Removed, -2),
+            (Path::new("main.rs"), FileChange::Changed, 2),
+            (Path::new("same.c"), FileChange::Changed, 0),
+        ]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn history_is_read_from_git() -> Result<()> {
+    let dir = tempdir()?;
+    git(dir.path(), &["init", "--quiet"], "")?;
+
+    fs::write(dir.path().join("main.rs"), "fn main() {}\n")?;
+    git(dir.path(), &["add", "."], "")?;
+    git(
+        dir.path(),
+        &["commit", "-qm", "one"],
+        "2024-01-01T10:00:00Z",
+    )?;
+    fs::write(dir.path().join("main.rs"), "fn main() {\n}\n")?;
+    fs::write(dir.path().join("build.py"), "x = 1\n")?;
+    git(dir.path(), &["add", "."], "")?;
+    git(
+        dir.path(),
+        &["commit", "-qm", "two"],
+        "2024-01-01T18:00:00Z",
+    )?;
+    git(dir.path(), &["tag", "v1"], "")?;
+    fs::create_dir(dir.path().join("vendor"))?;
+    fs::write(dir.path().join("vendor/lib.js"), "f();\n")?;
+    fs::write(
+        dir.path().join(".gitattributes"),
+        "vendor/** linguist-vendored\n",
+    )?;
+    git(dir.path(), &["add", "."], "")?;
+    git(
+        dir.path(),
+        &["commit", "-qm", "three"],
+        "2024-01-03T09:00:00Z",
+    )?;
+    // uncommitted changes are not counted
+    fs::write(dir.path().join("main.rs"), "")?;
+
+    let options = GetStatsOptions::default();
+    let mut counter = TreeCounter::new(&[dir.path().to_path_buf()], &options)?;
+    let commits = counter.repo().commits("HEAD", Sampling::Commit, None)?;
+    assert_eq!(commits.len(), 3);
+    assert_eq!(commits[0].date, "2024-01-01T10:00:00+00:00");
+    assert_eq!(commits[1].tags, vec!["v1".to_string()]);
+
+    let first = counter.count(&commits[0].id)?;
+    assert_eq!(first[0].path, dir.path());
+    assert_eq!(first[0].stats.total_loc, 1);
+    let last = counter.count(&commits[2].id)?;
+    assert_eq!(last[0].stats.by_lang["Rust"].loc, 2);
+    assert_eq!(last[0].stats.by_lang["Python"].loc, 1);
+    assert_eq!(last[0].stats.excluded["vendored"].files, 1);
+
+    let days = counter.repo().commits("HEAD", Sampling::Day, None)?;
+    assert_eq!(days.len(), 2);
+    assert_eq!(days[0].id, commits[1].id);
+    let weeks = counter.repo().commits("HEAD", Sampling::Week, None)?;
+    assert_eq!(weeks.len(), 1);
+    let tags = counter.repo().commits("HEAD", Sampling::Tag, None)?;
+    assert_eq!(tags.len(), 1);
+    assert_eq!(tags[0].id, commits[1].id);
+
+    Ok(())
+}
+
+#[test]
+fn rev_is_counted_like_a_clean_checkout() -> Result<()> {
+    let dir = tempdir()?;
+    git(dir.path(), &["init", "--quiet"], "")?;
+
+    fs::create_dir_all(dir.path().join("src/logs"))?;
+    fs::write(dir.path().join(".gitignore"), "*.log\nbuild/\n")?;
+    fs::write(dir.path().join("src/logs/.gitignore"), "!kept.log\n")?;
+    fs::write(dir.path().join("src/.ignore"), "generated.rs\n")?;
+    fs::write(dir.path().join("src/main.rs"), "// main\nfn main() {}\n")?;
+    fs::write(dir.path().join("src/generated.rs"), "fn generated() {}\n")?;
+    fs::write(dir.path().join("src/logs/kept.log"), "kept\n")?;
+    fs::write(dir.path().join("src/logs/skipped.log"), "skipped\n")?;
+    fs::create_dir(dir.path().join("build"))?;
+    fs::write(dir.path().join("build/out.rs"), "fn out() {}\n")?;
+    // symlinks are skipped in both
+    #[cfg(unix)]
+    std::os::unix::fs::symlink("main.rs", dir.path().join("src/link.rs"))?;
+    // ignored files that are committed anyway are skipped like in a checkout
+    git(dir.path(), &["add", "--force", "."], "")?;
+    git(
+        dir.path(),
+        &["commit", "-qm", "one"],
+        "2024-01-01T10:00:00Z",
+    )?;
+
+    let options = GetStatsOptions {
+        files: true,
+        ..Default::default()
+    };
+    let paths = [dir.path().to_path_buf(), dir.path().join("src")];
+    let checkout = get_stats_roots(&paths, &options)?;
+    assert_eq!(checkout[0].stats.total_loc, 3);
+
+    fs::write(dir.path().join("src/main.rs"), "")?;
+    fs::write(dir.path().join("src/lib.rs"), "pub fn f() {}\n")?;
+    assert_eq!(get_stats_rev(&paths, "HEAD", &options)?, checkout);
+
+    let src = get_stats_rev(&[dir.path().join("src")], "HEAD", &options)?;
+    assert_eq!(src[0].stats.total_loc, 3);
+    // paths removed from the working tree are still found in the commit
+    fs::remove_dir_all(dir.path().join("src"))?;
+    assert_eq!(
+        get_stats_rev(&[dir.path().join("src")], "HEAD", &options)?,
+        src
+    );
+    assert!(get_stats_rev(&paths, "nope", &options).is_err());
+
+    Ok(())
+}
+
+/// Runs git in `dir`, committing at `date` if it isn't empty.
+fn git(dir: &Path, args: &[&str], date: &str) -> Result<()> {
+    let mut command = Command::new("git");
+    command
+        .arg("-C")
+        .arg(dir)
+        .args([
+            "-c",
+            "user.name=loc-stats",
+            "-c",
+            "user.email=loc-stats@example.com",
+        ])
+        .args(args);
+    if !date.is_empty() {
+        command
+            .env("GIT_AUTHOR_DATE", date)
+            .env("GIT_COMMITTER_DATE", date);
+    }
+    let status = command.status()?;
+    anyhow::ensure!(status.success(), "git {:?} failed", args);
+    Ok(())
+}